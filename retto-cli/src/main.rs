@@ -13,6 +13,17 @@ pub enum DeviceKind {
     Cuda,
     #[cfg(feature = "backend-ort-directml")]
     DirectMl,
+    #[cfg(feature = "backend-ort-xnnpack")]
+    Xnnpack,
+    #[cfg(feature = "backend-ort-openvino")]
+    OpenVino,
+}
+
+#[cfg(feature = "quantize")]
+#[derive(ValueEnum, Clone, Debug)]
+pub enum QuantizePrecision {
+    Int8,
+    Fp16,
 }
 
 #[derive(Parser, Debug)]
@@ -26,13 +37,44 @@ pub struct Cli {
     rec_model_path: String,
     #[arg(long, default_value = "ppocr_keys_v1.txt")]
     rec_keys_path: String,
-    #[arg(short, long)]
-    images: String,
+    #[cfg_attr(
+        feature = "quantize",
+        arg(short, long, required_unless_present = "quantize_to")
+    )]
+    #[cfg_attr(not(feature = "quantize"), arg(short, long, required = true))]
+    images: Option<String>,
     #[arg(long, value_enum, default_value_t = DeviceKind::Cpu)]
     device: DeviceKind,
+    /// Threads of each ONNX Runtime session, one per physical core by default
+    #[arg(long)]
+    intra_threads: Option<usize>,
+    /// Directory caching the optimized models across runs
+    #[arg(long)]
+    optimized_model_cache: Option<String>,
+    /// Write int8 or fp16 copies of the det and rec models to this directory and exit
+    #[cfg(feature = "quantize")]
+    #[arg(long)]
+    quantize_to: Option<String>,
+    /// Precision of the copies written by `--quantize-to`
+    #[cfg(feature = "quantize")]
+    #[arg(long, value_enum, default_value_t = QuantizePrecision::Int8)]
+    quantize_precision: QuantizePrecision,
+    /// Also convert the cls model with `--quantize-to`
+    #[cfg(feature = "quantize")]
+    #[arg(long)]
+    quantize_cls: bool,
+    /// Python interpreter with the `onnxruntime` package (and `onnxconverter-common` for
+    /// fp16), used by `--quantize-to`
+    #[cfg(feature = "quantize")]
+    #[arg(long, default_value = "python3")]
+    python: String,
     #[cfg(any(feature = "backend-ort-cuda", feature = "backend-ort-directml"))]
     #[arg(long, default_value_t = 0)]
     device_id: i32,
+    /// Threads of the CPU execution provider, its own default when unset
+    #[cfg(any(feature = "backend-ort-xnnpack", feature = "backend-ort-openvino"))]
+    #[arg(long)]
+    provider_threads: Option<usize>,
     #[cfg(feature = "hf-hub")]
     #[arg(long, default_value = "true")]
     use_hf_hub: bool,
@@ -49,6 +91,10 @@ fn main() -> anyhow::Result<()> {
         DeviceKind::Cuda => RettoOrtWorkerDevice::Cuda(cli.device_id),
         #[cfg(feature = "backend-ort-directml")]
         DeviceKind::DirectMl => RettoOrtWorkerDevice::DirectML(cli.device_id),
+        #[cfg(feature = "backend-ort-xnnpack")]
+        DeviceKind::Xnnpack => RettoOrtWorkerDevice::XNNPACK(cli.provider_threads),
+        #[cfg(feature = "backend-ort-openvino")]
+        DeviceKind::OpenVino => RettoOrtWorkerDevice::OpenVINO(cli.provider_threads),
     };
     #[allow(unused_mut)] // false positive due to dynamic target cfg
     let mut models = RettoOrtWorkerModelProvider(RettoWorkerModelProvider {
@@ -64,12 +110,40 @@ fn main() -> anyhow::Result<()> {
         }
         false => {}
     }
+    #[cfg(feature = "quantize")]
+    if let Some(dir) = &cli.quantize_to {
+        let models = models.quantize(
+            dir,
+            &RettoOrtQuantizeConfig {
+                precision: match cli.quantize_precision {
+                    QuantizePrecision::Int8 => RettoOrtQuantization::Int8,
+                    QuantizePrecision::Fp16 => RettoOrtQuantization::Fp16,
+                },
+                cls: cli.quantize_cls,
+                python: cli.python.clone(),
+                ..Default::default()
+            },
+        )?;
+        tracing::info!("Quantized models written: {:?}", models.0);
+        return Ok(());
+    }
+    let session_options = RettoOrtSessionOptions {
+        intra_threads: cli.intra_threads,
+        ..Default::default()
+    };
     let cfg: RettoSessionConfig<RettoOrtWorker> = RettoSessionConfig {
-        worker_config: RettoOrtWorkerConfig { device, models },
+        worker_config: RettoOrtWorkerConfig {
+            device,
+            models,
+            det_session: session_options.clone(),
+            cls_session: session_options.clone(),
+            rec_session: session_options,
+            optimized_model_cache: cli.optimized_model_cache,
+        },
         ..Default::default()
     };
     let mut session = RettoSession::new(cfg)?;
-    let walkers = WalkDir::new(&cli.images);
+    let walkers = WalkDir::new(cli.images.unwrap_or_default());
     let files = walkers
         .into_iter()
         .filter_map(|e| e.ok())
@@ -9,6 +9,12 @@ pub enum RettoError {
     #[cfg(feature = "backend-ort")]
     #[error(transparent)]
     OrtError(#[from] ort::error::Error),
+    #[cfg(feature = "backend-candle")]
+    #[error(transparent)]
+    CandleError(#[from] candle_core::Error),
+    #[cfg(feature = "backend-tract")]
+    #[error(transparent)]
+    TractError(#[from] tract_onnx::prelude::TractError),
     #[error(transparent)]
     Utf8Error(#[from] std::string::FromUtf8Error),
     #[cfg(feature = "hf-hub")]
@@ -16,6 +22,17 @@ pub enum RettoError {
     HfHubError(#[from] hf_hub::api::sync::ApiError),
     #[error("Model not found: {0}")]
     ModelNotFoundError(String),
+    #[error("Metadata not found in model: {0}")]
+    ModelMetadataNotFoundError(String),
+    #[error("Model does not match the config: {0}")]
+    ModelConfigMismatchError(String),
+    #[error("Model checksum mismatch: {0}")]
+    ModelChecksumError(String),
+    #[error("Invalid language model: {0}")]
+    LanguageModelError(String),
+    #[cfg(feature = "quantize")]
+    #[error("Quantization failed: {0}")]
+    QuantizeError(String),
 }
 
 pub type RettoResult<T> = Result<T, RettoError>;
@@ -10,6 +10,7 @@ use ordered_float::OrderedFloat;
 use paste::paste;
 use std::cmp::{max, min};
 
+#[derive(Clone)]
 pub(crate) struct ImageHelper {
     inner: Option<RgbImage>,
     ori_h: usize,
@@ -70,6 +71,11 @@ impl ImageHelper {
         self.inner.take()
     }
 
+    #[inline]
+    pub fn rgb_image(&self) -> &RgbImage {
+        self.inner.as_ref().unwrap()
+    }
+
     #[inline]
     pub fn ori_size(&self) -> (usize, usize) {
         (self.ori_h, self.ori_w)
@@ -242,11 +248,60 @@ impl ImageHelper {
             Rgb::white(), // TODO: in opencv impl is cv2.BORDER_REPLICATE, not cv2.BORDER_CONSTANT
             &mut out,
         );
-        if (out.height() as f32) / (out.width() as f32) >= 1.5 {
+        if Self::is_vertical_crop(point) {
             return rotate270(&out);
         }
         out
     }
+
+    /// Whether [ImageHelper::get_crop_img] rotates the crop of this box by 270 degrees
+    pub fn is_vertical_crop(point: &PointBox<OrderedFloat<f32>>) -> bool {
+        let img_crop_width = max(point.width_brc(), point.width_tlc()).into_inner();
+        let img_crop_height = max(point.height_brc(), point.height_tlc()).into_inner();
+        let (w, h) = (img_crop_width as u32, img_crop_height as u32);
+        (h as f32) / (w as f32) >= 1.5
+    }
+
+    /// Returns a copy upscaled by `factor` (bicubic)
+    pub fn upscaled(&self, factor: f32) -> Self {
+        let image = self.inner.as_ref().unwrap();
+        let (w, h) = image.dimensions();
+        let (new_w, new_h) = (
+            ((w as f32 * factor).round() as u32).max(1),
+            ((h as f32 * factor).round() as u32).max(1),
+        );
+        Self::new_from_rgb_image(imageops::resize(
+            image,
+            new_w,
+            new_h,
+            imageops::FilterType::CatmullRom,
+        ))
+    }
+
+    /// Returns a copy whose luminance range is linearly stretched to `[0, 255]`
+    pub fn contrast_stretched(&self) -> Self {
+        let mut image = self.inner.clone().unwrap();
+        let (lo, hi) = image.pixels().fold((u8::MAX, u8::MIN), |(lo, hi), p| {
+            let [r, g, b] = p.0;
+            let luma = ((r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000) as u8;
+            (lo.min(luma), hi.max(luma))
+        });
+        if hi > lo {
+            let scale = 255.0 / (hi - lo) as f32;
+            image.pixels_mut().for_each(|p| {
+                p.0.iter_mut()
+                    .for_each(|c| *c = ((c.saturating_sub(lo)) as f32 * scale).min(255.0) as u8);
+            });
+        }
+        Self::new_from_rgb_image(image)
+    }
+
+    /// Returns a color-inverted copy
+    pub fn inverted(&self) -> Self {
+        let mut image = self.inner.clone().unwrap();
+        imageops::invert(&mut image);
+        Self::new_from_rgb_image(image)
+    }
 }
 
 macro_rules! impl_rotate {
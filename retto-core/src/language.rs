@@ -0,0 +1,244 @@
+use crate::processor::script::RecScript;
+use crate::serde::*;
+use crate::worker::RettoWorkerModelSource;
+
+/// Languages with a PaddleOCR recognition model, the det and cls models are shared
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Language {
+    #[default]
+    /// Simplified Chinese, English and digits
+    Chinese,
+    English,
+    Japanese,
+    Korean,
+    /// French, German, Spanish, Portuguese, Italian and other Latin script languages
+    Latin,
+    /// Russian, Ukrainian, Belarusian, Serbian and other Cyrillic script languages
+    Cyrillic,
+    /// Arabic, Persian, Uyghur, Urdu and other Arabic script languages
+    Arabic,
+    /// Hindi, Marathi, Nepali and other Devanagari script languages
+    Devanagari,
+    Tamil,
+}
+
+impl Language {
+    pub(crate) const HF_REPO: &'static str = "pk5ls20/PaddleModel";
+    pub(crate) const HF_PREFIX: &'static str = "retto/onnx";
+    /// Files published under [Language::HF_PREFIX], the ones `build.rs` downloads.
+    /// The other presets are read from the working directory.
+    pub(crate) const HF_HUB_FILES: [&'static str; 4] = [
+        "ch_PP-OCRv4_det_infer.onnx",
+        "ch_PP-OCRv4_rec_infer.onnx",
+        "ch_ppocr_mobile_v2.0_cls_infer.onnx",
+        "ppocr_keys_v1.txt",
+    ];
+
+    pub const ALL: [Language; 9] = [
+        Language::Chinese,
+        Language::English,
+        Language::Japanese,
+        Language::Korean,
+        Language::Latin,
+        Language::Cyrillic,
+        Language::Arabic,
+        Language::Devanagari,
+        Language::Tamil,
+    ];
+
+    /// File name of the PP-OCRv4 (PP-OCRv3 if there is no v4 release) rec model
+    pub fn v4_rec_model_file(&self) -> &'static str {
+        match self {
+            Language::Chinese => "ch_PP-OCRv4_rec_infer.onnx",
+            Language::English => "en_PP-OCRv4_rec_infer.onnx",
+            Language::Japanese => "japan_PP-OCRv3_rec_infer.onnx",
+            Language::Korean => "korean_PP-OCRv3_rec_infer.onnx",
+            Language::Latin => "latin_PP-OCRv3_rec_infer.onnx",
+            Language::Cyrillic => "cyrillic_PP-OCRv3_rec_infer.onnx",
+            Language::Arabic => "arabic_PP-OCRv3_rec_infer.onnx",
+            Language::Devanagari => "devanagari_PP-OCRv3_rec_infer.onnx",
+            Language::Tamil => "ta_PP-OCRv3_rec_infer.onnx",
+        }
+    }
+
+    /// File name of the character dictionary matching [Language::v4_rec_model_file]
+    pub fn v4_dict_file(&self) -> &'static str {
+        match self {
+            Language::Chinese => "ppocr_keys_v1.txt",
+            Language::English => "en_dict.txt",
+            Language::Japanese => "japan_dict.txt",
+            Language::Korean => "korean_dict.txt",
+            Language::Latin => "latin_dict.txt",
+            Language::Cyrillic => "cyrillic_dict.txt",
+            Language::Arabic => "arabic_dict.txt",
+            Language::Devanagari => "devanagari_dict.txt",
+            Language::Tamil => "ta_dict.txt",
+        }
+    }
+
+    /// Whether the rec model and the dictionary are listed in [Language::HF_HUB_FILES]
+    pub fn is_on_hf_hub(&self) -> bool {
+        Self::HF_HUB_FILES.contains(&self.v4_rec_model_file())
+            && Self::HF_HUB_FILES.contains(&self.v4_dict_file())
+    }
+
+    /// Scripts the rec model of this language is meant for, besides digits and punctuation
+    pub fn scripts(&self) -> &'static [RecScript] {
+        match self {
+            Language::Chinese => &[RecScript::Han, RecScript::Latin],
+            Language::English | Language::Latin => &[RecScript::Latin],
+            Language::Japanese => &[RecScript::Hiragana, RecScript::Katakana, RecScript::Han],
+            Language::Korean => &[RecScript::Hangul],
+            Language::Cyrillic => &[RecScript::Cyrillic],
+            Language::Arabic => &[RecScript::Arabic],
+            Language::Devanagari => &[RecScript::Devanagari],
+            Language::Tamil => &[RecScript::Tamil],
+        }
+    }
+
+    #[cfg(all(not(target_family = "wasm"), feature = "hf-hub"))]
+    pub(crate) fn hf_hub_source(file: &str) -> RettoWorkerModelSource {
+        RettoWorkerModelSource::HuggingFace {
+            repo: Self::HF_REPO.to_string(),
+            model: format!("{}/{file}", Self::HF_PREFIX),
+        }
+    }
+
+    /// Where `file` is resolved by default, HF Hub if enabled and the file is published there
+    /// (see [Language::HF_HUB_FILES]), a local path otherwise
+    #[cfg(not(target_family = "wasm"))]
+    pub(crate) fn default_source(file: &str) -> RettoWorkerModelSource {
+        #[cfg(feature = "hf-hub")]
+        if Self::HF_HUB_FILES.contains(&file) {
+            return Self::hf_hub_source(file);
+        }
+        RettoWorkerModelSource::Path(file.into())
+    }
+
+    /// Where the dictionary is resolved by default, see [Language::default_source]
+    #[cfg(not(target_family = "wasm"))]
+    pub fn v4_dict_source(&self) -> RettoWorkerModelSource {
+        Self::default_source(self.v4_dict_file())
+    }
+
+    /// Where the rec model is resolved by default, see [Language::default_source]
+    #[cfg(not(target_family = "wasm"))]
+    pub fn v4_rec_model_source(&self) -> RettoWorkerModelSource {
+        Self::default_source(self.v4_rec_model_file())
+    }
+}
+
+/// PP-OCRv5 det / rec model size, both recognize simplified and traditional Chinese,
+/// English, Japanese and pinyin with a single dictionary
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum PpOcrV5Model {
+    #[default]
+    /// Lightweight models for CPU and edge devices
+    Mobile,
+    /// Larger and more accurate models, preferably run on GPU
+    Server,
+}
+
+impl PpOcrV5Model {
+    /// File name of the character dictionary shared by the PP-OCRv5 rec models
+    pub const DICT_FILE: &'static str = "ppocrv5_dict.txt";
+
+    pub fn det_model_file(&self) -> &'static str {
+        match self {
+            PpOcrV5Model::Mobile => "PP-OCRv5_mobile_det_infer.onnx",
+            PpOcrV5Model::Server => "PP-OCRv5_server_det_infer.onnx",
+        }
+    }
+
+    pub fn rec_model_file(&self) -> &'static str {
+        match self {
+            PpOcrV5Model::Mobile => "PP-OCRv5_mobile_rec_infer.onnx",
+            PpOcrV5Model::Server => "PP-OCRv5_server_rec_infer.onnx",
+        }
+    }
+
+    /// Where the dictionary is resolved by default, the working directory as the PP-OCRv5
+    /// files are not published on HF Hub
+    #[cfg(not(target_family = "wasm"))]
+    pub fn dict_source() -> RettoWorkerModelSource {
+        Language::default_source(Self::DICT_FILE)
+    }
+}
+
+#[cfg(all(test, not(target_family = "wasm")))]
+mod tests {
+    use super::*;
+    use crate::processor::prelude::*;
+    use rstest::*;
+    use std::collections::HashSet;
+
+    fn location(source: RettoWorkerModelSource) -> String {
+        match source {
+            RettoWorkerModelSource::Path(path) => path,
+            #[cfg(feature = "hf-hub")]
+            RettoWorkerModelSource::HuggingFace { repo, model } => format!("{repo}/{model}"),
+            other => panic!("unexpected preset source {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_hf_hub_files_match_build_script() {
+        let build_script = include_str!("../build.rs");
+        assert!(
+            Language::HF_HUB_FILES
+                .iter()
+                .all(|file| build_script.contains(&format!("\"{file}\"")))
+        );
+        // from_hf_hub_v4_default relies on it
+        assert!(Language::default().is_on_hf_hub());
+    }
+
+    #[test]
+    fn test_preset_files_are_distinct() {
+        let rec_models = Language::ALL.map(|l| l.v4_rec_model_file());
+        let dicts = Language::ALL.map(|l| l.v4_dict_file());
+        assert_eq!(
+            rec_models.iter().collect::<HashSet<_>>().len(),
+            Language::ALL.len()
+        );
+        assert_eq!(
+            dicts.iter().collect::<HashSet<_>>().len(),
+            Language::ALL.len()
+        );
+        assert!(Language::ALL.iter().all(|l| !l.scripts().is_empty()));
+    }
+
+    #[rstest]
+    #[case::published(Language::Chinese, true)]
+    #[case::local(Language::Korean, false)]
+    fn test_v4_sources(#[case] language: Language, #[case] published: bool) {
+        let expected = |file: &str| match cfg!(feature = "hf-hub") && published {
+            true => format!("{}/{}/{file}", Language::HF_REPO, Language::HF_PREFIX),
+            false => file.to_string(),
+        };
+        assert_eq!(
+            location(language.v4_rec_model_source()),
+            expected(language.v4_rec_model_file())
+        );
+        assert_eq!(
+            location(language.v4_dict_source()),
+            expected(language.v4_dict_file())
+        );
+        let route = RecRouteConfig::from_language(language);
+        assert_eq!(
+            location(route.model),
+            expected(language.v4_rec_model_file())
+        );
+        assert_eq!(route.scripts, language.scripts());
+    }
+
+    #[test]
+    fn test_v5_sources_are_local() {
+        assert_eq!(
+            location(PpOcrV5Model::dict_source()),
+            PpOcrV5Model::DICT_FILE
+        );
+    }
+}
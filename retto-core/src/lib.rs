@@ -5,6 +5,7 @@ pub mod error;
 #[cfg(feature = "hf-hub")]
 mod hf_hub_helper;
 mod image_helper;
+pub mod language;
 pub mod points;
 pub mod processor;
 pub mod serde;
@@ -13,6 +14,7 @@ pub mod worker;
 
 pub mod prelude {
     pub use crate::error::{RettoError, RettoResult};
+    pub use crate::language::{Language, PpOcrV5Model};
     pub use crate::processor::prelude::*;
     pub use crate::session::*;
     pub use crate::worker::prelude::*;
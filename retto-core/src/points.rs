@@ -49,6 +49,7 @@ where
     }
 }
 
+#[derive(Clone)]
 #[cfg_attr(
     feature = "serde",
     derive(Serialize, Deserialize),
@@ -176,6 +177,23 @@ where
         Point::new(center_x, center_y)
     }
 
+    /// Returns the same box with its points cyclically shifted by `n`, i.e. the point at
+    /// index `n` becomes the new top-left point
+    pub(crate) fn shift_start(&self, n: usize) -> Self {
+        let mut inner = self.inner;
+        inner.rotate_left(n % 4);
+        PointBox { inner }
+    }
+
+    pub(crate) fn map_points<F>(&self, f: F) -> Self
+    where
+        F: FnMut(Point<T>) -> Point<T>,
+    {
+        PointBox {
+            inner: self.inner.map(f),
+        }
+    }
+
     pub(crate) fn scale_and_clip(&mut self, bitmap_w: f64, bitmap_h: f64, ori_w: f64, ori_h: f64)
     where
         T: Num + NumCast + Copy + Ord + Debug + AsPrimitive<f64>,
@@ -192,4 +210,35 @@ where
             p.y = NumCast::from(y1).unwrap();
         })
     }
+
+    /// Push every edge of the (roughly rectangular) box outwards, in the same way as
+    /// the DB unclip step, using `distance = area * ratio / perimeter`.
+    /// The result is clipped to `[0, max_w - 1] x [0, max_h - 1]`.
+    pub(crate) fn unclip_rect(&self, ratio: f64, max_w: f64, max_h: f64) -> Self
+    where
+        T: Num + NumCast + Copy + Ord + Debug + AsPrimitive<f64>,
+    {
+        let (w, h): (f64, f64) = (self.width_tlc().as_(), self.height_tlc().as_());
+        if w <= 0.0 || h <= 0.0 {
+            return self.clone();
+        }
+        let distance = w * h * ratio / (2.0 * (w + h));
+        let (tl, tr, bl) = (self.tl(), self.tr(), self.bl());
+        let (tlx, tly): (f64, f64) = (tl.x.as_(), tl.y.as_());
+        let (trx, try_): (f64, f64) = (tr.x.as_(), tr.y.as_());
+        let (blx, bly): (f64, f64) = (bl.x.as_(), bl.y.as_());
+        let u = ((trx - tlx) / w, (try_ - tly) / w);
+        let v = ((blx - tlx) / h, (bly - tly) / h);
+        let dirs = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)];
+        let mut inner = self.inner;
+        inner.iter_mut().zip(dirs).for_each(|(p, (su, sv))| {
+            let x0: f64 = p.x.as_();
+            let y0: f64 = p.y.as_();
+            let x1 = (x0 + distance * (su * u.0 + sv * v.0)).clamp(0.0, max_w - 1f64);
+            let y1 = (y0 + distance * (su * u.1 + sv * v.1)).clamp(0.0, max_h - 1f64);
+            p.x = NumCast::from(x1).unwrap();
+            p.y = NumCast::from(y1).unwrap();
+        });
+        PointBox { inner }
+    }
 }
@@ -1,6 +1,11 @@
+pub mod bidi;
 pub mod cls_processor;
+pub mod ctc_decoder;
 pub mod det_processor;
 pub mod rec_processor;
+pub mod script;
+pub mod spell_corrector;
+pub mod text_post_processor;
 
 use crate::error::RettoResult;
 
@@ -44,7 +49,12 @@ pub(crate) trait Processor: ProcessorInner {
 
 pub mod prelude {
     pub(crate) use super::Processor;
+    pub use super::bidi::RecTextDirection;
     pub use super::cls_processor::*;
+    pub use super::ctc_decoder::*;
     pub use super::det_processor::*;
     pub use super::rec_processor::*;
+    pub use super::script::*;
+    pub use super::spell_corrector::{RecCorrectionMode, RecCorrectorConfig};
+    pub use super::text_post_processor::*;
 }
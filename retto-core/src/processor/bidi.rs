@@ -0,0 +1,73 @@
+use crate::serde::*;
+use unicode_bidi::{BidiClass, BidiInfo, Level, bidi_class};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum RecTextDirection {
+    #[default]
+    LeftToRight,
+    /// Arabic, Hebrew, Persian, ... as the dominant script of the line
+    RightToLeft,
+}
+
+impl RecTextDirection {
+    /// Direction of a line from its strong characters, the majority wins
+    pub fn of(text: &str) -> Self {
+        let balance = text
+            .chars()
+            .map(|c| match bidi_class(c) {
+                BidiClass::L => -1,
+                BidiClass::R | BidiClass::AL => 1,
+                _ => 0,
+            })
+            .sum::<i32>();
+        match balance > 0 {
+            true => RecTextDirection::RightToLeft,
+            false => RecTextDirection::LeftToRight,
+        }
+    }
+}
+
+/// Convert the visual (timestep) order of a decoded line into logical order.
+///
+/// CTC emits characters from left to right, so RTL runs come out reversed. Running the
+/// Unicode Bidi Algorithm over the visual string reverses them back, while digits and
+/// Latin runs embedded in RTL text keep their order.
+pub(crate) fn visual_to_logical(text: &str) -> (String, RecTextDirection) {
+    let direction = RecTextDirection::of(text);
+    let has_rtl = text
+        .chars()
+        .any(|c| matches!(bidi_class(c), BidiClass::R | BidiClass::AL));
+    if !has_rtl {
+        return (text.to_string(), direction);
+    }
+    let level = match direction {
+        RecTextDirection::LeftToRight => Level::ltr(),
+        RecTextDirection::RightToLeft => Level::rtl(),
+    };
+    let info = BidiInfo::new(text, Some(level));
+    let logical = info
+        .paragraphs
+        .iter()
+        .map(|para| info.reorder_line(para, para.range.clone()))
+        .collect();
+    (logical, direction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::*;
+
+    #[rstest]
+    #[case("玩原神 v5", "玩原神 v5", RecTextDirection::LeftToRight)]
+    #[case("םלוע םולש", "שלום עולם", RecTextDirection::RightToLeft)]
+    #[case("123 םולש", "שלום 123", RecTextDirection::RightToLeft)]
+    fn test_visual_to_logical(
+        #[case] visual: &str,
+        #[case] logical: &str,
+        #[case] direction: RecTextDirection,
+    ) {
+        assert_eq!(visual_to_logical(visual), (logical.to_string(), direction));
+    }
+}
@@ -12,8 +12,11 @@ use std::fmt::Display;
 #[derive(Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ClsProcessorConfig {
-    /// Prediction scale
+    /// Prediction scale `[C, H, W]`, checked against the fixed dimensions of the model
     pub image_shape: [usize; 3],
+    /// Take the fixed dimensions of the model input instead of `image_shape`,
+    /// which still sets the dynamic ones
+    pub infer_image_shape: bool,
     /// Batch size predicted by direction classifier
     pub batch_num: usize,
     /// Predict threshold. If the model predicts a result of 180 degrees and the score is
@@ -28,6 +31,7 @@ impl Default for ClsProcessorConfig {
     fn default() -> Self {
         ClsProcessorConfig {
             image_shape: [3, 48, 192],
+            infer_image_shape: false,
             batch_num: 6,
             thresh: 0.9,
             label: vec![0, 180],
@@ -38,6 +42,7 @@ impl Default for ClsProcessorConfig {
 #[derive(Debug)]
 pub(crate) struct ClsProcessor<'p> {
     config: &'p ClsProcessorConfig,
+    image_shape: [usize; 3],
 }
 
 #[derive(Debug, Default)]
@@ -91,8 +96,11 @@ impl ProcessorInnerIO for ClsProcessor<'_> {
 }
 
 impl<'a> ClsProcessor<'a> {
-    pub fn new(config: &'a ClsProcessorConfig) -> Self {
-        ClsProcessor { config }
+    pub fn new(config: &'a ClsProcessorConfig, image_shape: [usize; 3]) -> Self {
+        ClsProcessor {
+            config,
+            image_shape,
+        }
     }
 }
 
@@ -143,7 +151,7 @@ impl<'p> Processor for ClsProcessor<'p> {
                     .iter()
                     .map(|&i| {
                         crop_images[i]
-                            .resize_norm_image(self.config.image_shape, None)
+                            .resize_norm_image(self.image_shape, None)
                             .insert_axis(Axis(0))
                     })
                     .collect::<Vec<_>>();
@@ -171,3 +179,43 @@ impl<'p> Processor for ClsProcessor<'p> {
         Ok(ClsProcessorResult(final_res))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::worker::mock_worker::{RettoMockWorker, RettoMockWorkerConfig};
+    use crate::worker::{RettoInnerWorker, RettoWorker};
+    use image::Rgb;
+    use rstest::*;
+
+    #[rstest]
+    #[case::flipped(0.9, [false, true])]
+    #[case::below_thresh(0.99, [false, false])]
+    fn test_cls_flip(#[case] thresh: f32, #[case] rotated: [bool; 2]) {
+        let lines = RettoMockWorkerConfig::default()
+            .with_line([0.1, 0.1, 0.6, 0.2], "hello", false)
+            .with_line([0.1, 0.5, 0.6, 0.6], "world", true);
+        let mut crops = (0..2)
+            .map(|line| lines.line_crop(line, 200, 40))
+            .collect::<Vec<_>>();
+        let mut worker = RettoMockWorker::new(lines).unwrap();
+        let config = ClsProcessorConfig {
+            thresh,
+            ..Default::default()
+        };
+        let cls = ClsProcessor::new(&config, config.image_shape);
+        let res = cls.process(&mut crops, |i| worker.cls(i)).unwrap();
+        // the label does not depend on the threshold, only the rotation of the crop
+        assert!(res.0.iter().map(|r| r.label.label).eq([0, 180]));
+        for (crop, rotated) in crops.iter().zip(rotated) {
+            let marked = match rotated {
+                true => (199, 39),
+                false => (0, 0),
+            };
+            assert_eq!(
+                *crop.rgb_image().get_pixel(marked.0, marked.1),
+                Rgb([255; 3])
+            );
+        }
+    }
+}
@@ -0,0 +1,774 @@
+use crate::error::{RettoError, RettoResult};
+use crate::serde::*;
+use crate::worker::{RettoWorkerModelResolvedSource, RettoWorkerModelSource};
+use ndarray::prelude::*;
+use ordered_float::OrderedFloat;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum RecDecoderConfig {
+    #[default]
+    /// Take the most probable character at each timestep
+    Greedy,
+    /// CTC prefix beam search over the full rec output
+    BeamSearch(RecBeamSearchConfig),
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RecBeamSearchConfig {
+    /// Number of prefixes kept after each timestep
+    pub beam_width: usize,
+    /// Characters whose probability is lower than this threshold are not expanded
+    pub prune_thresh: f32,
+    /// Maximum number of characters expanded at each timestep
+    pub cutoff_top_n: usize,
+    /// Word list (one word per line). When set, every space-separated word of the decoded
+    /// text must be one of these words, or the line falls back to greedy decoding.
+    pub lexicon: Option<RettoWorkerModelSource>,
+    /// Character n-gram language model in ARPA format
+    pub lm: Option<RettoWorkerModelSource>,
+    /// Weight of the language model score
+    pub lm_weight: f32,
+    /// Bonus added for every decoded character, counterbalances the language model
+    pub length_bonus: f32,
+    /// Domain terms the decoding is biased towards
+    pub hotwords: Vec<RecHotword>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RecHotword {
+    pub text: String,
+    /// Log score added for every matched character. Partial matches lose the bonus when
+    /// the next character breaks them, so only complete hotwords are favored.
+    pub boost: f32,
+}
+
+impl RecHotword {
+    pub fn new(text: impl Into<String>, boost: f32) -> Self {
+        RecHotword {
+            text: text.into(),
+            boost,
+        }
+    }
+}
+
+impl Default for RecBeamSearchConfig {
+    fn default() -> Self {
+        RecBeamSearchConfig {
+            beam_width: 10,
+            prune_thresh: 1e-3,
+            cutoff_top_n: 40,
+            lexicon: None,
+            lm: None,
+            lm_weight: 0.5,
+            length_bonus: 0.0,
+            hotwords: Vec::new(),
+        }
+    }
+}
+
+/// Character level language model used by the beam search decoder
+pub trait RecLanguageModel: Debug + Send + Sync {
+    /// Natural log probability of `next` following `context`,
+    /// which holds the previously decoded characters of the line
+    fn log_prob(&self, context: &[&str], next: &str) -> f32;
+    /// Natural log probability of the line ending after the given context, 0 unless overridden
+    fn end_log_prob(&self, context: &[&str]) -> f32 {
+        let _ = context;
+        0.0
+    }
+}
+
+/// Back-off n-gram model loaded from an ARPA file whose tokens are single characters,
+/// `<space>` stands for the space character
+#[derive(Debug)]
+pub struct ArpaRecLanguageModel {
+    order: usize,
+    vocab: HashMap<String, u32>,
+    /// n-gram -> (log10 probability, log10 back-off weight)
+    ngrams: HashMap<Vec<u32>, (f32, f32)>,
+    unk_log10_prob: f32,
+}
+
+impl ArpaRecLanguageModel {
+    const BOS: &'static str = "<s>";
+    const EOS: &'static str = "</s>";
+    const UNK: &'static str = "<unk>";
+
+    pub fn from_source(source: RettoWorkerModelSource) -> RettoResult<Self> {
+        let content = match source.resolve()? {
+            #[cfg(not(target_family = "wasm"))]
+            RettoWorkerModelResolvedSource::Path(path) => std::fs::read_to_string(path)?,
+            RettoWorkerModelResolvedSource::Blob(blob) => String::from_utf8(blob)?,
+        };
+        Self::parse(&content)
+    }
+
+    pub fn parse(content: &str) -> RettoResult<Self> {
+        let mut vocab = HashMap::new();
+        let mut ngrams = HashMap::new();
+        let mut order = 0;
+        let mut current = None;
+        for (line_no, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line == "\\data\\" || line.starts_with("ngram ") {
+                continue;
+            }
+            if line == "\\end\\" {
+                break;
+            }
+            if let Some(n) = line
+                .strip_prefix('\\')
+                .and_then(|l| l.strip_suffix("-grams:"))
+            {
+                let n = n.parse::<usize>().map_err(|_| {
+                    RettoError::LanguageModelError(format!("Invalid section at line {line_no}"))
+                })?;
+                order = order.max(n);
+                current = Some(n);
+                continue;
+            }
+            let Some(n) = current else {
+                continue;
+            };
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            if fields.len() != n + 1 && fields.len() != n + 2 {
+                return Err(RettoError::LanguageModelError(format!(
+                    "Invalid {n}-gram at line {line_no}"
+                )));
+            }
+            let parse = |s: &str| {
+                s.parse::<f32>().map_err(|_| {
+                    RettoError::LanguageModelError(format!("Invalid weight at line {line_no}"))
+                })
+            };
+            let log_prob = parse(fields[0])?;
+            let backoff = fields.get(n + 1).map(|s| parse(s)).transpose()?;
+            let ids = fields[1..=n]
+                .iter()
+                .map(|&token| {
+                    let token = match token {
+                        "<space>" => " ",
+                        t => t,
+                    };
+                    let next_id = vocab.len() as u32;
+                    *vocab.entry(token.to_string()).or_insert(next_id)
+                })
+                .collect::<Vec<_>>();
+            ngrams.insert(ids, (log_prob, backoff.unwrap_or(0.0)));
+        }
+        if order == 0 {
+            return Err(RettoError::LanguageModelError(
+                "No n-gram found in the ARPA file".to_string(),
+            ));
+        }
+        let unk_log10_prob = vocab
+            .get(Self::UNK)
+            .and_then(|&id| ngrams.get(&vec![id]))
+            .map_or(-10.0, |&(p, _)| p);
+        Ok(ArpaRecLanguageModel {
+            order,
+            vocab,
+            ngrams,
+            unk_log10_prob,
+        })
+    }
+
+    /// The last `order - 1` tokens of `<s>` followed by `context`
+    fn token_ids(&self, context: &[&str]) -> Vec<Option<u32>> {
+        let history = self.order - 1;
+        let mut ids = Vec::with_capacity(history);
+        if context.len() < history {
+            ids.push(self.vocab.get(Self::BOS).copied());
+        }
+        ids.extend(
+            context[context.len().saturating_sub(history)..]
+                .iter()
+                .map(|t| self.vocab.get(*t).copied()),
+        );
+        ids
+    }
+
+    fn log10_prob(&self, context: &[Option<u32>], next: Option<u32>) -> f32 {
+        let Some(next) = next else {
+            return self.unk_log10_prob;
+        };
+        let mut backoff = 0.0;
+        // longest known history first
+        for start in 0..=context.len() {
+            let history = &context[start..];
+            let Some(history) = history.iter().copied().collect::<Option<Vec<_>>>() else {
+                continue;
+            };
+            let mut ngram = history.clone();
+            ngram.push(next);
+            if let Some(&(p, _)) = self.ngrams.get(&ngram) {
+                return p + backoff;
+            }
+            if !history.is_empty() {
+                backoff += self.ngrams.get(&history).map_or(0.0, |&(_, b)| b);
+            }
+        }
+        self.unk_log10_prob + backoff
+    }
+}
+
+impl RecLanguageModel for ArpaRecLanguageModel {
+    fn log_prob(&self, context: &[&str], next: &str) -> f32 {
+        let ids = self.token_ids(context);
+        self.log10_prob(&ids, self.vocab.get(next).copied()) * std::f32::consts::LN_10
+    }
+
+    fn end_log_prob(&self, context: &[&str]) -> f32 {
+        let ids = self.token_ids(context);
+        self.log10_prob(&ids, self.vocab.get(Self::EOS).copied()) * std::f32::consts::LN_10
+    }
+}
+
+#[derive(Debug, Default)]
+struct LexiconNode {
+    children: HashMap<String, usize>,
+    terminal: bool,
+}
+
+/// Trie of lexicon words over characters, it does not depend on the dictionary
+/// so the decoders of every rec model share it
+#[derive(Debug)]
+struct Lexicon {
+    nodes: Vec<LexiconNode>,
+}
+
+impl Lexicon {
+    const ROOT: usize = 0;
+
+    fn from_source(source: RettoWorkerModelSource) -> RettoResult<Self> {
+        let content = match source.resolve()? {
+            #[cfg(not(target_family = "wasm"))]
+            RettoWorkerModelResolvedSource::Path(path) => std::fs::read_to_string(path)?,
+            RettoWorkerModelResolvedSource::Blob(blob) => String::from_utf8(blob)?,
+        };
+        Ok(Self::new(&content))
+    }
+
+    fn new(content: &str) -> Self {
+        let mut nodes = vec![LexiconNode::default()];
+        for word in content.lines().map(str::trim).filter(|w| !w.is_empty()) {
+            let mut node = Self::ROOT;
+            for c in word.chars().map(String::from) {
+                node = match nodes[node].children.get(&c) {
+                    Some(&child) => child,
+                    None => {
+                        nodes.push(LexiconNode::default());
+                        let child = nodes.len() - 1;
+                        nodes[node].children.insert(c, child);
+                        child
+                    }
+                };
+            }
+            nodes[node].terminal = true;
+        }
+        Lexicon { nodes }
+    }
+
+    /// Trie state after appending `token`, `None` if the word can no longer be completed
+    fn advance(&self, node: usize, token: &str) -> Option<usize> {
+        match token == " " {
+            true => self.is_word_end(node).then_some(Self::ROOT),
+            false => self.nodes[node].children.get(token).copied(),
+        }
+    }
+
+    fn is_word_end(&self, node: usize) -> bool {
+        node == Self::ROOT || self.nodes[node].terminal
+    }
+}
+
+#[derive(Debug, Default)]
+struct HotwordNode {
+    children: HashMap<usize, usize>,
+    /// Bonus of the prefix ending at this node, the highest one if several hotwords share it
+    bonus: f32,
+    terminal: bool,
+}
+
+/// Trie of hotwords over dictionary indices
+#[derive(Debug)]
+struct Hotwords {
+    nodes: Vec<HotwordNode>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct HotwordState {
+    node: usize,
+    /// Bonus of the hotwords completed so far
+    committed: f32,
+}
+
+impl Hotwords {
+    const ROOT: usize = 0;
+
+    fn new(hotwords: &[RecHotword], dict: &[String]) -> Self {
+        let index = dict
+            .iter()
+            .enumerate()
+            .skip(1) // blank
+            .map(|(i, c)| (c.as_str(), i))
+            .collect::<HashMap<_, _>>();
+        let mut nodes = vec![HotwordNode::default()];
+        for hotword in hotwords.iter().filter(|h| !h.text.is_empty()) {
+            let Some(ids) = hotword
+                .text
+                .chars()
+                .map(|c| index.get(c.to_string().as_str()).copied())
+                .collect::<Option<Vec<_>>>()
+            else {
+                tracing::warn!("Hotword {} contains unknown characters, skip", hotword.text);
+                continue;
+            };
+            let mut node = Self::ROOT;
+            for (depth, id) in ids.into_iter().enumerate() {
+                let bonus = hotword.boost * (depth + 1) as f32;
+                node = match nodes[node].children.get(&id) {
+                    Some(&child) => {
+                        nodes[child].bonus = nodes[child].bonus.max(bonus);
+                        child
+                    }
+                    None => {
+                        nodes.push(HotwordNode {
+                            bonus,
+                            ..Default::default()
+                        });
+                        let child = nodes.len() - 1;
+                        nodes[node].children.insert(id, child);
+                        child
+                    }
+                };
+            }
+            nodes[node].terminal = true;
+        }
+        Hotwords { nodes }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.nodes.len() == 1
+    }
+
+    /// Bonus of `state` if the line ended here, partial matches are dropped
+    fn settled(&self, state: HotwordState) -> f32 {
+        let node = &self.nodes[state.node];
+        state.committed + if node.terminal { node.bonus } else { 0.0 }
+    }
+
+    /// Bonus of `state`, including the partial match
+    fn score(&self, state: HotwordState) -> f32 {
+        state.committed + self.nodes[state.node].bonus
+    }
+
+    fn advance(&self, state: HotwordState, token: usize) -> HotwordState {
+        let step = |node: usize| self.nodes[node].children.get(&token).copied();
+        let (node, committed) = match step(state.node) {
+            Some(child) => (child, state.committed),
+            // The partial match is broken, keep it only if it is a complete hotword
+            None => {
+                let committed = self.settled(state);
+                (step(Self::ROOT).unwrap_or(Self::ROOT), committed)
+            }
+        };
+        match self.nodes[node].children.is_empty() && node != Self::ROOT {
+            true => HotwordState {
+                node: Self::ROOT,
+                committed: committed + self.nodes[node].bonus,
+            },
+            false => HotwordState { node, committed },
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Beam {
+    /// Log probability of the paths ending with blank
+    p_b: f32,
+    /// Log probability of the paths ending with the last character
+    p_nb: f32,
+    /// Weighted language model score
+    lm: f32,
+    /// Lexicon trie state
+    node: usize,
+    /// Hotword trie state
+    hotword: HotwordState,
+    /// (dictionary index, probability, timestep) of every character
+    chars: Vec<(usize, f32, usize)>,
+}
+
+#[inline]
+fn log_sum_exp(a: f32, b: f32) -> f32 {
+    let (hi, lo) = if a > b { (a, b) } else { (b, a) };
+    match hi == f32::NEG_INFINITY {
+        true => hi,
+        false => hi + (lo - hi).exp().ln_1p(),
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct CtcBeamSearchDecoder {
+    config: RecBeamSearchConfig,
+    lexicon: Option<Arc<Lexicon>>,
+    hotwords: Option<Hotwords>,
+    lm: Option<Arc<dyn RecLanguageModel>>,
+}
+
+impl CtcBeamSearchDecoder {
+    fn new(config: &RecBeamSearchConfig, dict: &[String]) -> RettoResult<Self> {
+        let lexicon = config
+            .lexicon
+            .clone()
+            .map(|source| Lexicon::from_source(source).map(Arc::new))
+            .transpose()?;
+        let lm = match &config.lm {
+            Some(source) => Some(Arc::new(ArpaRecLanguageModel::from_source(source.clone())?)
+                as Arc<dyn RecLanguageModel>),
+            None => None,
+        };
+        Ok(Self::with_resources(config, dict, lexicon, lm))
+    }
+
+    fn with_resources(
+        config: &RecBeamSearchConfig,
+        dict: &[String],
+        lexicon: Option<Arc<Lexicon>>,
+        lm: Option<Arc<dyn RecLanguageModel>>,
+    ) -> Self {
+        let hotwords = Some(Hotwords::new(&config.hotwords, dict)).filter(|h| !h.is_empty());
+        CtcBeamSearchDecoder {
+            config: config.clone(),
+            lexicon,
+            hotwords,
+            lm,
+        }
+    }
+
+    fn score(&self, beam: &Beam) -> f32 {
+        log_sum_exp(beam.p_b, beam.p_nb)
+            + beam.lm
+            + self.config.length_bonus * beam.chars.len() as f32
+            + self
+                .hotwords
+                .as_ref()
+                .map_or(0.0, |h| h.score(beam.hotword))
+    }
+
+    /// Returns the (dictionary index, probability, timestep) of every decoded character,
+    /// or `None` if no prefix satisfies the lexicon
+    pub(crate) fn search(
+        &self,
+        preds: ArrayView2<f32>,
+        dict: &[String],
+        ignored_tokens: &[usize],
+    ) -> Option<Vec<(usize, f32, usize)>> {
+        let lm_term = |prefix: &[usize], next: usize| match &self.lm {
+            Some(lm) => {
+                let context = prefix.iter().map(|&i| dict[i].as_str()).collect::<Vec<_>>();
+                self.config.lm_weight * lm.log_prob(&context, &dict[next])
+            }
+            None => 0.0,
+        };
+        let mut beams: HashMap<Vec<usize>, Beam> = HashMap::from([(
+            Vec::new(),
+            Beam {
+                p_b: 0.0,
+                p_nb: f32::NEG_INFINITY,
+                lm: 0.0,
+                node: Lexicon::ROOT,
+                hotword: HotwordState::default(),
+                chars: Vec::new(),
+            },
+        )]);
+        for (t, probs) in preds.axis_iter(Axis(0)).enumerate() {
+            let mut candidates = probs
+                .iter()
+                .enumerate()
+                .filter(|&(i, &p)| {
+                    i != 0 && p >= self.config.prune_thresh && !ignored_tokens.contains(&i)
+                })
+                .map(|(i, &p)| (i, p))
+                .collect::<Vec<_>>();
+            candidates.sort_by_key(|&(_, p)| Reverse(OrderedFloat(p)));
+            candidates.truncate(self.config.cutoff_top_n);
+            let blank_lp = probs[0].ln();
+            let mut next: HashMap<Vec<usize>, Beam> = HashMap::with_capacity(beams.len() * 2);
+            for (prefix, beam) in &beams {
+                let total = log_sum_exp(beam.p_b, beam.p_nb);
+                let stay = next.entry(prefix.clone()).or_insert_with(|| Beam {
+                    p_b: f32::NEG_INFINITY,
+                    p_nb: f32::NEG_INFINITY,
+                    ..beam.clone()
+                });
+                stay.p_b = log_sum_exp(stay.p_b, total + blank_lp);
+                if let Some(&last) = prefix.last() {
+                    // repeated character without blank in between is collapsed
+                    stay.p_nb = log_sum_exp(stay.p_nb, beam.p_nb + probs[last].ln());
+                }
+                for &(c, p) in &candidates {
+                    let node = match &self.lexicon {
+                        // Words of the lexicon are separated by spaces
+                        Some(lexicon) => match lexicon.advance(beam.node, &dict[c]) {
+                            Some(node) => node,
+                            None => continue,
+                        },
+                        None => Lexicon::ROOT,
+                    };
+                    let extend_p = match prefix.last() == Some(&c) {
+                        true => beam.p_b,
+                        false => total,
+                    } + p.ln();
+                    let mut extended = prefix.clone();
+                    extended.push(c);
+                    let entry = next.entry(extended).or_insert_with(|| {
+                        let mut chars = beam.chars.clone();
+                        chars.push((c, p, t));
+                        Beam {
+                            p_b: f32::NEG_INFINITY,
+                            p_nb: f32::NEG_INFINITY,
+                            lm: beam.lm + lm_term(prefix, c),
+                            node,
+                            hotword: self
+                                .hotwords
+                                .as_ref()
+                                .map_or(beam.hotword, |h| h.advance(beam.hotword, c)),
+                            chars,
+                        }
+                    });
+                    entry.p_nb = log_sum_exp(entry.p_nb, extend_p);
+                }
+            }
+            let mut ranked = next.into_iter().collect::<Vec<_>>();
+            ranked.sort_by_key(|(_, beam)| Reverse(OrderedFloat(self.score(beam))));
+            ranked.truncate(self.config.beam_width);
+            beams = ranked.into_iter().collect();
+        }
+        beams
+            .into_iter()
+            // The empty prefix reaches no word either, `None` lets the caller fall back to greedy
+            .filter(|(prefix, beam)| {
+                self.lexicon
+                    .as_ref()
+                    .is_none_or(|lexicon| !prefix.is_empty() && lexicon.is_word_end(beam.node))
+            })
+            .map(|(prefix, beam)| {
+                let end = match &self.lm {
+                    Some(lm) => {
+                        let context = prefix.iter().map(|&i| dict[i].as_str()).collect::<Vec<_>>();
+                        self.config.lm_weight * lm.end_log_prob(&context)
+                    }
+                    None => 0.0,
+                };
+                // Unfinished hotwords do not count
+                let hotword = self
+                    .hotwords
+                    .as_ref()
+                    .map_or(0.0, |h| h.settled(beam.hotword) - h.score(beam.hotword));
+                (OrderedFloat(self.score(&beam) + end + hotword), beam)
+            })
+            .max_by_key(|(score, _)| *score)
+            .map(|(_, beam)| beam.chars)
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum RecDecoder {
+    Greedy,
+    BeamSearch(Box<CtcBeamSearchDecoder>),
+}
+
+impl RecDecoder {
+    pub fn new(config: &RecDecoderConfig, dict: &[String]) -> RettoResult<Self> {
+        match config {
+            RecDecoderConfig::Greedy => Ok(RecDecoder::Greedy),
+            RecDecoderConfig::BeamSearch(cfg) => Ok(RecDecoder::BeamSearch(Box::new(
+                CtcBeamSearchDecoder::new(cfg, dict)?,
+            ))),
+        }
+    }
+
+    /// Decoder of the same config for another dictionary, the lexicon and the language model
+    /// are shared instead of being loaded again
+    pub fn for_dict(&self, dict: &[String]) -> Self {
+        match self {
+            RecDecoder::Greedy => RecDecoder::Greedy,
+            RecDecoder::BeamSearch(decoder) => {
+                RecDecoder::BeamSearch(Box::new(CtcBeamSearchDecoder::with_resources(
+                    &decoder.config,
+                    dict,
+                    decoder.lexicon.clone(),
+                    decoder.lm.clone(),
+                )))
+            }
+        }
+    }
+
+    /// Replace the language model of the beam search decoder,
+    /// returns `false` if the decoder is not a beam search decoder
+    pub fn set_language_model(&mut self, lm: Box<dyn RecLanguageModel>) -> bool {
+        match self {
+            RecDecoder::Greedy => false,
+            RecDecoder::BeamSearch(decoder) => {
+                decoder.lm = Some(Arc::from(lm));
+                true
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::*;
+
+    const ARPA: &str = "\\data\\
+ngram 1=4
+ngram 2=2
+
+\\1-grams:
+-1.0 <s> -0.5
+-0.5 a -0.3
+-0.8 b -0.2
+-1.0 </s>
+
+\\2-grams:
+-0.1 a b
+-0.2 <s> a
+
+\\end\\
+";
+
+    fn dict() -> Vec<String> {
+        ["blank", "a", "b", " "].map(String::from).to_vec()
+    }
+
+    fn preds(rows: &[[f32; 4]]) -> Array2<f32> {
+        Array2::from_shape_vec((rows.len(), 4), rows.concat()).unwrap()
+    }
+
+    fn decode(decoder: &CtcBeamSearchDecoder, preds: &Array2<f32>) -> Option<String> {
+        let dict = dict();
+        decoder
+            .search(preds.view(), &dict, &[0])
+            .map(|path| path.iter().map(|&(i, _, _)| dict[i].as_str()).collect())
+    }
+
+    #[rstest]
+    #[case(&[[0.1, 0.8, 0.05, 0.05], [0.8, 0.1, 0.05, 0.05], [0.1, 0.1, 0.75, 0.05]], "ab")]
+    #[case(&[[0.1, 0.8, 0.05, 0.05], [0.1, 0.8, 0.05, 0.05], [0.8, 0.1, 0.05, 0.05]], "a")]
+    #[case(&[[0.1, 0.8, 0.05, 0.05], [0.8, 0.1, 0.05, 0.05], [0.1, 0.8, 0.05, 0.05]], "aa")]
+    fn test_beam_search_collapses_like_ctc(#[case] rows: &[[f32; 4]], #[case] expected: &str) {
+        let decoder = CtcBeamSearchDecoder::new(&RecBeamSearchConfig::default(), &dict()).unwrap();
+        assert_eq!(decode(&decoder, &preds(rows)).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_beam_search_lexicon() {
+        let config = RecBeamSearchConfig {
+            lexicon: Some(RettoWorkerModelSource::Blob(b"ab\nba".to_vec())),
+            ..Default::default()
+        };
+        let decoder = CtcBeamSearchDecoder::new(&config, &dict()).unwrap();
+        // greedy would be "aa"
+        let rows = [
+            [0.1, 0.8, 0.05, 0.05],
+            [0.8, 0.1, 0.05, 0.05],
+            [0.1, 0.5, 0.35, 0.05],
+        ];
+        assert_eq!(decode(&decoder, &preds(&rows)).unwrap(), "ab");
+    }
+
+    #[test]
+    fn test_beam_search_lexicon_no_match() {
+        let config = RecBeamSearchConfig {
+            lexicon: Some(RettoWorkerModelSource::Blob(b"bb".to_vec())),
+            ..Default::default()
+        };
+        let decoder = CtcBeamSearchDecoder::new(&config, &dict()).unwrap();
+        // no beam reaches "bb", the caller falls back to greedy "aa"
+        let rows = [
+            [0.1, 0.9, 0.0, 0.0],
+            [0.9, 0.1, 0.0, 0.0],
+            [0.1, 0.9, 0.0, 0.0],
+        ];
+        assert_eq!(decode(&decoder, &preds(&rows)), None);
+    }
+
+    #[test]
+    fn test_beam_search_lexicon_space_lookup() {
+        // the space is not the last entry of this dictionary
+        let dict = ["blank", "a", " ", "b"].map(String::from).to_vec();
+        let config = RecBeamSearchConfig {
+            lexicon: Some(RettoWorkerModelSource::Blob(b"a\nb".to_vec())),
+            ..Default::default()
+        };
+        let decoder = CtcBeamSearchDecoder::new(&config, &dict).unwrap();
+        let rows = [
+            [0.1, 0.8, 0.05, 0.05],
+            [0.1, 0.05, 0.8, 0.05],
+            [0.1, 0.05, 0.05, 0.8],
+        ];
+        let path = decoder.search(preds(&rows).view(), &dict, &[0]).unwrap();
+        let text = path
+            .iter()
+            .map(|&(i, _, _)| dict[i].as_str())
+            .collect::<String>();
+        assert_eq!(text, "a b");
+    }
+
+    #[test]
+    fn test_for_dict_shares_resources() {
+        let config = RecDecoderConfig::BeamSearch(RecBeamSearchConfig {
+            lexicon: Some(RettoWorkerModelSource::Blob(b"ab".to_vec())),
+            lm: Some(RettoWorkerModelSource::Blob(ARPA.as_bytes().to_vec())),
+            ..Default::default()
+        });
+        let decoder = RecDecoder::new(&config, &dict()).unwrap();
+        let other = decoder.for_dict(&["blank", "b", "a"].map(String::from));
+        let (RecDecoder::BeamSearch(a), RecDecoder::BeamSearch(b)) = (&decoder, &other) else {
+            panic!("beam search decoder expected");
+        };
+        assert!(Arc::ptr_eq(
+            a.lexicon.as_ref().unwrap(),
+            b.lexicon.as_ref().unwrap()
+        ));
+        assert!(Arc::ptr_eq(a.lm.as_ref().unwrap(), b.lm.as_ref().unwrap()));
+    }
+
+    #[test]
+    fn test_beam_search_hotwords() {
+        let rows = [
+            [0.1, 0.8, 0.05, 0.05],
+            [0.8, 0.1, 0.05, 0.05],
+            [0.1, 0.55, 0.3, 0.05],
+        ];
+        let decoder = CtcBeamSearchDecoder::new(&RecBeamSearchConfig::default(), &dict()).unwrap();
+        assert_eq!(decode(&decoder, &preds(&rows)).unwrap(), "aa");
+        let config = RecBeamSearchConfig {
+            hotwords: vec![RecHotword::new("ab", 0.5)],
+            ..Default::default()
+        };
+        let decoder = CtcBeamSearchDecoder::new(&config, &dict()).unwrap();
+        assert_eq!(decode(&decoder, &preds(&rows)).unwrap(), "ab");
+    }
+
+    #[test]
+    fn test_arpa_backoff() {
+        let lm = ArpaRecLanguageModel::parse(ARPA).unwrap();
+        let ln = |log10: f32| log10 * std::f32::consts::LN_10;
+        assert!((lm.log_prob(&[], "a") - ln(-0.2)).abs() < 1e-5);
+        assert!((lm.log_prob(&["a"], "b") - ln(-0.1)).abs() < 1e-5);
+        // back-off from `b a` to `a`
+        assert!((lm.log_prob(&["b"], "a") - ln(-0.2 - 0.5)).abs() < 1e-5);
+        assert!((lm.end_log_prob(&["b"]) - ln(-0.2 - 1.0)).abs() < 1e-5);
+    }
+}
@@ -137,6 +137,21 @@ fn trans_dilation_kernel(kernel: &Array2<usize>) -> Mask {
     Mask::from_image(&gray_kernel, anchor_x, anchor_y)
 }
 
+impl DetProcessorConfig {
+    /// Text detection defaults of the PaddleOCR 3.x inference pipeline for the PP-OCRv5 det
+    /// models: `limit_side_len: 64` with [LimitType::Min], which only upscales images whose
+    /// shortest side is below 64, `box_thresh: 0.6` and `unclip_ratio: 1.5`
+    pub fn pp_ocr_v5() -> Self {
+        DetProcessorConfig {
+            limit_side_len: 64,
+            limit_type: LimitType::Min,
+            box_thresh: 0.6,
+            unclip_ratio: 1.5,
+            ..Default::default()
+        }
+    }
+}
+
 /// PreProcess
 impl<'a> DetProcessor<'a> {
     pub fn new(config: &'a DetProcessorConfig, ori_h: usize, ori_w: usize) -> RettoResult<Self> {
@@ -352,3 +367,32 @@ impl<'p> Processor for DetProcessor<'p> {
         Ok(post_processed)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::worker::mock_worker::{RettoMockWorker, RettoMockWorkerConfig};
+    use crate::worker::{RettoInnerWorker, RettoWorker};
+    use rstest::*;
+
+    #[rstest]
+    #[case((400, 300), [0.1, 0.1, 0.6, 0.2])]
+    #[case((640, 480), [0.25, 0.4, 0.9, 0.5])]
+    fn test_det_box_round_trip(#[case] (w, h): (usize, usize), #[case] rect: [f32; 4]) {
+        let lines = RettoMockWorkerConfig::default().with_line(rect, "hello", false);
+        let image =
+            ImageHelper::new_from_raw_img_flow(lines.render(w as u32, h as u32).unwrap()).unwrap();
+        let mut worker = RettoMockWorker::new(lines).unwrap();
+        let config = DetProcessorConfig::default();
+        let det = DetProcessor::new(&config, h, w).unwrap();
+        let res = det
+            .process(image.array_view().unwrap(), |i| worker.det(i))
+            .unwrap();
+        assert_eq!(res.0.len(), 1);
+        let boxes = &res.0[0].boxes;
+        let [x0, y0, x1, y1] = rect;
+        let close = |a: OrderedFloat<f32>, b: f32| (a.into_inner() - b).abs() < 4.0;
+        assert!(close(boxes.tl().x, x0 * w as f32) && close(boxes.tl().y, y0 * h as f32));
+        assert!(close(boxes.br().x, x1 * w as f32) && close(boxes.br().y, y1 * h as f32));
+    }
+}
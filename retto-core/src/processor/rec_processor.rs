@@ -1,21 +1,30 @@
 use crate::error::{RettoError, RettoResult};
 use crate::image_helper::ImageHelper;
+use crate::language::{Language, PpOcrV5Model};
+use crate::points::{Point, PointBox};
+use crate::processor::bidi::RecTextDirection;
+use crate::processor::ctc_decoder::{RecDecoder, RecDecoderConfig};
+use crate::processor::script::{RecLanguageTag, RecScript};
+use crate::processor::spell_corrector::RecCorrectorConfig;
+use crate::processor::text_post_processor::RecTextNormalization;
 use crate::processor::{Processor, ProcessorInner, ProcessorInnerIO, ProcessorInnerRes};
 use crate::serde::*;
-use crate::worker::{RettoWorkerModelResolvedSource, RettoWorkerModelSource};
+use crate::worker::{RettoInnerWorker, RettoWorkerModelResolvedSource, RettoWorkerModelSource};
+use imageproc::geometric_transformations::Projection;
 use ndarray::prelude::*;
 use ndarray::{Zip, concatenate};
 use ndarray_stats::QuantileExt;
 use ordered_float::OrderedFloat;
 use std::cmp::{Reverse, max};
+use std::collections::HashSet;
 
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum RecCharacterDictProvider {
     /// From external sources (not embedded in the model)
     OutSide(RettoWorkerModelSource),
-    /// TODO: From the model itself
-    Inline(), // TODO: from onnx model itself?
+    /// From the `character` metadata of the rec model itself (newer PaddleOCR ONNX exports)
+    Inline(),
 }
 
 #[derive(Debug)]
@@ -26,18 +35,63 @@ pub(crate) struct RecCharacter {
 }
 
 impl RecCharacter {
-    pub fn new(dict: RecCharacterDictProvider, ignored_tokens: Vec<usize>) -> RettoResult<Self> {
+    const INLINE_DICT_KEY: &'static str = "character";
+
+    pub fn dict(&self) -> &[String] {
+        &self.inner
+    }
+
+    /// Whether each dictionary entry can be decoded under `charset`,
+    /// `None` if every entry can be decoded
+    pub fn charset_mask(&self, charset: &RecCharset) -> Option<Vec<bool>> {
+        let (chars, allow) = match charset {
+            RecCharset::All => return None,
+            RecCharset::Allow(chars) => (chars, true),
+            RecCharset::Deny(chars) => (chars, false),
+        };
+        let chars = chars.chars().collect::<HashSet<_>>();
+        let mask = self
+            .inner
+            .iter()
+            .enumerate()
+            .map(|(idx, entry)| match idx {
+                0 => true, // blank
+                _ => entry.chars().all(|c| chars.contains(&c)) == allow,
+            })
+            .collect();
+        Some(mask)
+    }
+
+    pub fn new<W>(
+        dict: RecCharacterDictProvider,
+        ignored_tokens: Vec<usize>,
+        use_space_char: bool,
+        worker: &W,
+        model: usize,
+    ) -> RettoResult<Self>
+    where
+        W: RettoInnerWorker,
+    {
         let content = match dict {
             RecCharacterDictProvider::OutSide(res) => match res.resolve()? {
                 #[cfg(not(target_family = "wasm"))]
                 RettoWorkerModelResolvedSource::Path(path) => std::fs::read_to_string(path)?,
                 RettoWorkerModelResolvedSource::Blob(blob) => String::from_utf8(blob)?,
             },
-            RecCharacterDictProvider::Inline() => todo!("Load dict from onnx model itself!"),
+            RecCharacterDictProvider::Inline() => worker
+                .rec_metadata(model, Self::INLINE_DICT_KEY)?
+                .ok_or_else(|| {
+                    RettoError::ModelMetadataNotFoundError(format!(
+                        "`{}` of the rec model",
+                        Self::INLINE_DICT_KEY
+                    ))
+                })?,
         };
         let mut dict: Vec<String> = content.lines().map(str::trim).map(str::to_owned).collect();
         // insert_special_char
-        dict.push(" ".to_string());
+        if use_space_char {
+            dict.push(" ".to_string());
+        }
         dict.insert(0, "blank".to_string());
         Ok(Self {
             inner: dict,
@@ -47,54 +101,355 @@ impl RecCharacter {
 
     fn decode(
         &self,
+        preds: &Array3<f32>,
         text_index: &Array2<usize>,
         text_prob: &Array2<f32>, // TODO: let it be optional
         wh_ratio_list: &[OrderedFloat<f32>],
         max_wh_ratio: OrderedFloat<f32>,
-        remove_duplicate: bool,
-        return_word_box: bool, // TODO: implement this
-    ) -> Vec<(String, f32)> {
+        options: RecDecodeOptions,
+    ) -> Vec<RecProcessorSingleResult> {
+        let RecDecodeOptions {
+            decoder,
+            remove_duplicate,
+            return_word_box,
+            char_top_k,
+            charset_mask,
+        } = options;
         text_index
             .axis_iter(Axis(0))
             .zip(text_prob.axis_iter(Axis(0)))
-            .map(|(token_indices, prob)| {
-                let mut selection = token_indices.mapv(|i| i != 0);
-                if remove_duplicate {
-                    Zip::from(selection.slice_mut(s![1..]))
-                        .and(token_indices.slice(s![1..]))
-                        .and(token_indices.slice(s![..-1]))
-                        .for_each(|sel, &curr, &prev| {
-                            *sel = *sel && curr != prev;
-                        });
-                }
-                self.ignored_tokens.iter().for_each(|ignored| {
-                    Zip::from(&mut selection)
-                        .and(token_indices)
-                        .for_each(|sel, &idx| {
-                            *sel = *sel && idx != *ignored;
-                        });
-                });
+            .zip(preds.axis_iter(Axis(0)))
+            .zip(wh_ratio_list)
+            .map(|(((token_indices, prob), line_preds), wh_ratio)| {
                 debug_assert_eq!(token_indices.len(), prob.len());
                 let text_len = token_indices.len();
-                let pre_res = selection
+                // (dictionary index, probability, timestep)
+                let path = match decoder {
+                    RecDecoder::Greedy => None,
+                    RecDecoder::BeamSearch(beam) => {
+                        beam.search(line_preds, &self.inner, &self.ignored_tokens)
+                    }
+                }
+                .unwrap_or_else(|| self.greedy_path(token_indices, prob, remove_duplicate));
+                let char_scores = match char_top_k {
+                    Some(k) => path
+                        .iter()
+                        .map(|&(idx, p, col)| RecCharScore {
+                            text: self.inner[idx].clone(),
+                            score: p,
+                            candidates: self.top_k_candidates(
+                                line_preds.row(col),
+                                idx,
+                                k,
+                                charset_mask,
+                            ),
+                        })
+                        .collect(),
+                    None => Vec::new(),
+                };
+                // (character, probability, timestep)
+                let chars = path
                     .iter()
-                    .zip(token_indices.iter())
-                    .zip(prob.iter())
-                    .filter_map(|((&sel, &idx), &p)| match sel {
-                        true => Some((self.inner[idx].clone(), p)),
-                        false => None,
-                    })
-                    .fold(
-                        (String::with_capacity(text_len), 0.0, 0u32),
-                        |(mut acc_str, acc_sum, sum), (seg, p)| {
-                            acc_str.push_str(&seg);
-                            (acc_str, acc_sum + p, sum + 1)
-                        },
-                    );
-                (pre_res.0, pre_res.1 / pre_res.2 as f32)
+                    .map(|&(idx, p, col)| (self.inner[idx].as_str(), p, col))
+                    .collect::<Vec<_>>();
+                let text = chars.iter().map(|&(c, _, _)| c).collect::<String>();
+                let score = match chars.len() {
+                    0 => 0.0,
+                    n => chars.iter().map(|&(_, p, _)| p).sum::<f32>() / n as f32,
+                };
+                let word_results = match return_word_box {
+                    true => {
+                        // Only the unpadded part of the input is covered by the characters
+                        let valid_ratio = (*wh_ratio / max_wh_ratio).into_inner().min(1.0);
+                        get_word_results(&chars, text_len as f32 * valid_ratio)
+                    }
+                    false => Vec::new(),
+                };
+                RecProcessorSingleResult {
+                    score,
+                    char_scores,
+                    word_results,
+                    direction: RecTextDirection::of(&text),
+                    text,
+                    raw_text: None,
+                    language: None,
+                }
             })
             .collect::<Vec<_>>()
     }
+
+    fn greedy_path(
+        &self,
+        token_indices: ArrayView1<usize>,
+        prob: ArrayView1<f32>,
+        remove_duplicate: bool,
+    ) -> Vec<(usize, f32, usize)> {
+        let mut selection = token_indices.mapv(|i| i != 0);
+        if remove_duplicate {
+            Zip::from(selection.slice_mut(s![1..]))
+                .and(token_indices.slice(s![1..]))
+                .and(token_indices.slice(s![..-1]))
+                .for_each(|sel, &curr, &prev| {
+                    *sel = *sel && curr != prev;
+                });
+        }
+        self.ignored_tokens.iter().for_each(|ignored| {
+            Zip::from(&mut selection)
+                .and(token_indices)
+                .for_each(|sel, &idx| {
+                    *sel = *sel && idx != *ignored;
+                });
+        });
+        selection
+            .iter()
+            .zip(token_indices.iter())
+            .zip(prob.iter())
+            .enumerate()
+            .filter_map(|(col, ((&sel, &idx), &p))| match sel {
+                true => Some((idx, p, col)),
+                false => None,
+            })
+            .collect()
+    }
+
+    /// The `k` most probable characters at one timestep, except the emitted one,
+    /// the blank, the ignored tokens and the characters masked out by the charset
+    fn top_k_candidates(
+        &self,
+        probs: ArrayView1<f32>,
+        emitted: usize,
+        k: usize,
+        charset_mask: Option<&[bool]>,
+    ) -> Vec<RecCharCandidate> {
+        let mut candidates = probs
+            .iter()
+            .enumerate()
+            .filter(|&(idx, _)| idx != 0 && idx != emitted && !self.ignored_tokens.contains(&idx))
+            .filter(|&(idx, _)| charset_mask.is_none_or(|mask| mask[idx]))
+            .map(|(idx, &p)| (idx, OrderedFloat(p)))
+            .collect::<Vec<_>>();
+        if k < candidates.len() {
+            candidates.select_nth_unstable_by_key(k, |&(_, p)| Reverse(p));
+            candidates.truncate(k);
+        }
+        candidates.sort_by_key(|&(_, p)| Reverse(p));
+        candidates
+            .into_iter()
+            .map(|(idx, p)| RecCharCandidate {
+                text: self.inner[idx].clone(),
+                score: p.into_inner(),
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RecDecodeOptions<'d> {
+    decoder: &'d RecDecoder,
+    remove_duplicate: bool,
+    return_word_box: bool,
+    char_top_k: Option<usize>,
+    charset_mask: Option<&'d [bool]>,
+}
+
+fn word_kind(c: &str, prev: Option<RecWordKind>, next: Option<&str>) -> Option<RecWordKind> {
+    let first = c.chars().next()?;
+    match (first, RecScript::of(first)) {
+        // the prolonged sound mark is shared by both kanas
+        (_, Some(RecScript::Han | RecScript::Hiragana | RecScript::Katakana)) | ('ー', _) => {
+            Some(RecWordKind::Cjk)
+        }
+        // other scripts, like Hangul, separate their words with spaces
+        (_, Some(_)) => Some(RecWordKind::AlphaNum),
+        _ if first.is_alphanumeric() => Some(RecWordKind::AlphaNum),
+        // keep words like `state-of-the-art` together
+        ('-', _) if prev == Some(RecWordKind::AlphaNum) => Some(RecWordKind::AlphaNum),
+        // and floating point numbers
+        ('.', _)
+            if prev == Some(RecWordKind::AlphaNum)
+                && next.is_some_and(|n| n.starts_with(|n: char| n.is_ascii_digit())) =>
+        {
+            Some(RecWordKind::AlphaNum)
+        }
+        _ => None,
+    }
+}
+
+/// Build word and character boxes from the timestep of each decoded character, the boxes are
+/// relative to the crop and normalized to `[0, 1]` until
+/// [RecProcessorSingleResult::project_word_boxes] is called.
+fn get_word_results(chars: &[(&str, f32, usize)], col_num: f32) -> Vec<RecWordResult> {
+    // #region get_word_info
+    let mut words: Vec<(RecWordKind, Vec<(&str, usize)>)> = Vec::new();
+    let mut prev = None;
+    for (i, &(c, _, col)) in chars.iter().enumerate() {
+        let kind = word_kind(c, prev, chars.get(i + 1).map(|&(n, _, _)| n));
+        if let Some(kind) = kind {
+            match words.last_mut() {
+                Some((_, word)) if prev == Some(kind) => word.push((c, col)),
+                _ => words.push((kind, vec![(c, col)])),
+            }
+        }
+        prev = kind;
+    }
+    // #region cal_ocr_word_box
+    let cell_width = 1.0 / col_num.max(1.0);
+    let cjk_widths = words
+        .iter()
+        .filter(|(kind, word)| *kind == RecWordKind::Cjk && word.len() > 1)
+        .map(|(_, word)| {
+            let seq_len = (word[word.len() - 1].1 - word[0].1 + 1) as f32 * cell_width;
+            seq_len / (word.len() - 1) as f32
+        })
+        .collect::<Vec<_>>();
+    let avg_char_width = match cjk_widths.len() {
+        0 => 1.0 / chars.len().max(1) as f32,
+        n => cjk_widths.iter().sum::<f32>() / n as f32,
+    };
+    let span_box = |x0: f32, x1: f32| {
+        let (x0, x1) = (
+            OrderedFloat(x0.clamp(0.0, 1.0)),
+            OrderedFloat(x1.clamp(0.0, 1.0)),
+        );
+        let (y0, y1) = (OrderedFloat(0.0), OrderedFloat(1.0));
+        PointBox::new_from_clockwise([
+            Point::new(x0, y0),
+            Point::new(x1, y0),
+            Point::new(x1, y1),
+            Point::new(x0, y1),
+        ])
+    };
+    words
+        .into_iter()
+        .flat_map(|(kind, word)| match kind {
+            RecWordKind::Cjk => word
+                .into_iter()
+                .map(|(c, col)| {
+                    let center = (col as f32 + 0.5) * cell_width;
+                    let boxes =
+                        span_box(center - avg_char_width / 2.0, center + avg_char_width / 2.0);
+                    RecWordResult {
+                        text: c.to_string(),
+                        kind,
+                        boxes: boxes.clone(),
+                        char_results: vec![RecCharResult {
+                            text: c.to_string(),
+                            boxes,
+                        }],
+                    }
+                })
+                .collect::<Vec<_>>(),
+            RecWordKind::AlphaNum => {
+                let cols = word.iter().map(|&(_, col)| col as f32).collect::<Vec<_>>();
+                let x0 = cols[0] * cell_width;
+                let x1 = (cols[cols.len() - 1] + 1.0) * cell_width;
+                // characters are split in the middle of two adjacent timesteps
+                let bounds = std::iter::once(x0)
+                    .chain(
+                        cols.windows(2)
+                            .map(|w| (w[0] + w[1] + 1.0) / 2.0 * cell_width),
+                    )
+                    .chain(std::iter::once(x1))
+                    .collect::<Vec<_>>();
+                let char_results = word
+                    .iter()
+                    .zip(bounds.windows(2))
+                    .map(|(&(c, _), b)| RecCharResult {
+                        text: c.to_string(),
+                        boxes: span_box(b[0], b[1]),
+                    })
+                    .collect();
+                vec![RecWordResult {
+                    text: word.iter().map(|&(c, _)| c).collect(),
+                    kind,
+                    boxes: span_box(x0, x1),
+                    char_results,
+                }]
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum RecCharset {
+    #[default]
+    /// Every character of the dictionary can be decoded
+    All,
+    /// Only the characters of this string can be decoded, e.g. `"0123456789"` for meter readings
+    Allow(String),
+    /// The characters of this string are never decoded
+    Deny(String),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum RecBatchStrategy {
+    #[default]
+    /// `batch_num` crops per batch, each batch is padded to its widest crop
+    Fixed,
+    /// Crops are grouped by padded width, rounded up to a multiple of `bucket_width`, so that
+    /// short lines are not padded to the width of long ones. A batch holds crops of a single
+    /// bucket, as many as fit in `max_batch_width` padded columns (batch size × padded width),
+    /// `batch_num` is ignored.
+    Bucketed {
+        bucket_width: usize,
+        max_batch_width: usize,
+    },
+}
+
+impl RecBatchStrategy {
+    /// 64 pixel buckets, up to 16 crops of the default width per batch
+    pub fn bucketed() -> Self {
+        RecBatchStrategy::Bucketed {
+            bucket_width: 64,
+            max_batch_width: 16 * 320,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum RecRetryStrategy {
+    /// Re-crop the detected box after pushing its edges outwards with this extra unclip ratio
+    Unclip(f32),
+    /// Upscale the crop by this factor before recognition
+    Upscale(f32),
+    /// Linearly stretch the luminance range of the crop to `[0, 255]`
+    ContrastStretch,
+    /// Invert the crop colors (light text on a dark background)
+    Invert,
+    /// Rotate the crop by 180 degrees, overriding the direction classifier
+    Rotate180,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RecRetryConfig {
+    /// Lines whose score is lower than this threshold are recognized again.
+    pub score_thresh: f32,
+    /// Alternative preprocessing, tried in order until a line reaches `score_thresh`.
+    /// The best-scoring decode of each line is kept.
+    pub strategies: Vec<RecRetryStrategy>,
+    /// Maximum number of extra crops recognized in a single run, the lowest-scoring
+    /// lines are retried first.
+    pub max_attempts: usize,
+}
+
+impl Default for RecRetryConfig {
+    fn default() -> Self {
+        RecRetryConfig {
+            score_thresh: 0.8,
+            strategies: vec![
+                RecRetryStrategy::Unclip(0.5),
+                RecRetryStrategy::Upscale(2.0),
+                RecRetryStrategy::ContrastStretch,
+                RecRetryStrategy::Invert,
+                RecRetryStrategy::Rotate180,
+            ],
+            max_attempts: 16,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -102,24 +457,53 @@ impl RecCharacter {
 pub struct RecProcessorConfig {
     /// Identify the provider of the model dictionary source
     pub character_source: RecCharacterDictProvider,
-    /// Image size during recognition
+    /// Whether the space character is appended to the dictionary,
+    /// must match how the rec model was trained
+    pub use_space_char: bool,
+    /// Image size `[C, H, W]` during recognition, checked against the fixed dimensions of
+    /// the model
     pub image_shape: [usize; 3],
+    /// Take the fixed dimensions of the model input instead of `image_shape`,
+    /// which still sets the dynamic ones
+    pub infer_image_shape: bool,
     /// Batch size of recognition
     pub batch_num: usize,
+    /// How crops are grouped into batches
+    pub batching: RecBatchStrategy,
+    /// Whether to return the word and character boxes of each line
+    pub return_word_box: bool,
+    /// Decoding method applied to the rec output
+    pub decoder: RecDecoderConfig,
+    /// Characters that can be decoded, the others are masked out of the rec output before
+    /// decoding and never show up as candidates. Every timestep is renormalized over the
+    /// allowed characters. Can be overridden per call with [crate::session::RettoRunOptions].
+    pub charset: RecCharset,
+    /// Return the probability of every emitted character, together with up to this many
+    /// alternative characters at the same position. Disabled when `None`, unless `corrector`
+    /// is set as it needs them, see [RecCorrectorConfig::CHAR_TOP_K].
+    pub char_top_k: Option<usize>,
+    /// Second recognition pass for low-score lines, disabled when `None`
+    pub retry: Option<RecRetryConfig>,
+    /// Normalizations applied in order to the text of every line, before the hooks added with
+    /// [crate::session::RettoSession::add_text_post_processor]
+    pub text_normalization: Vec<RecTextNormalization>,
+    /// Snap the recognized text to a closed vocabulary, before `text_normalization`.
+    /// Disabled when `None`.
+    pub corrector: Option<RecCorrectorConfig>,
+    /// Convert right-to-left runs (Arabic, Hebrew, ...) from the visual order they are
+    /// decoded in to logical order, following the Unicode Bidi Algorithm
+    pub bidi_reorder: bool,
+    /// Tag every line with its script and probable language, see
+    /// [crate::session::RettoSession::set_rec_language_classifier] to tell apart the
+    /// languages sharing a script
+    pub identify_language: bool,
 }
 
 impl Default for RecProcessorConfig {
     fn default() -> Self {
-        #[cfg(all(feature = "hf-hub", not(target_family = "wasm")))]
+        #[cfg(not(target_family = "wasm"))]
         let character_source =
-            RecCharacterDictProvider::OutSide(RettoWorkerModelSource::HuggingFace {
-                repo: "pk5ls20/PaddleModel".into(),
-                model: "retto/onnx/ppocr_keys_v1.txt".to_string(),
-            });
-        #[cfg(all(not(feature = "hf-hub"), not(target_family = "wasm")))]
-        let character_source = RecCharacterDictProvider::OutSide(RettoWorkerModelSource::Path(
-            "ppocr_keys_v1.txt".into(),
-        ));
+            RecCharacterDictProvider::OutSide(Language::Chinese.v4_dict_source());
         #[cfg(all(feature = "download-models", target_family = "wasm"))]
         let character_source = RecCharacterDictProvider::OutSide(RettoWorkerModelSource::Blob(
             include_bytes!("../../models/ppocr_keys_v1.txt").to_vec(),
@@ -129,8 +513,88 @@ impl Default for RecProcessorConfig {
             RecCharacterDictProvider::OutSide(RettoWorkerModelSource::Blob(Vec::new()));
         RecProcessorConfig {
             character_source,
+            use_space_char: true,
             image_shape: [3, 48, 320],
+            infer_image_shape: false,
             batch_num: 6,
+            batching: RecBatchStrategy::default(),
+            return_word_box: false,
+            decoder: RecDecoderConfig::default(),
+            charset: RecCharset::default(),
+            char_top_k: None,
+            retry: None,
+            text_normalization: Vec::new(),
+            corrector: None,
+            bidi_reorder: true,
+            identify_language: false,
+        }
+    }
+}
+
+impl RecProcessorConfig {
+    /// Default config using the dictionary of `language`,
+    /// pair it with [crate::worker::RettoWorkerModelProviderBuilder::from_hf_hub_v4_language]
+    /// or [crate::worker::RettoWorkerModelProviderBuilder::from_local_v4_language_path]
+    #[cfg(not(target_family = "wasm"))]
+    pub fn from_language(language: Language) -> Self {
+        RecProcessorConfig {
+            character_source: RecCharacterDictProvider::OutSide(language.v4_dict_source()),
+            ..Default::default()
+        }
+    }
+
+    /// Default config of the PP-OCRv5 rec models,
+    /// pair it with [crate::worker::RettoWorkerModelProviderBuilder::from_local_v5_path]
+    #[cfg(not(target_family = "wasm"))]
+    pub fn pp_ocr_v5() -> Self {
+        RecProcessorConfig {
+            character_source: RecCharacterDictProvider::OutSide(PpOcrV5Model::dict_source()),
+            ..Default::default()
+        }
+    }
+}
+
+/// Extra rec model of a session, lines are routed to it by script or by
+/// [crate::session::RettoSession::set_rec_router]
+///
+/// Routing by script costs a second rec pass: the primary model reads every line first to
+/// tell its script and score, then each routed line is recognized again by its route. A
+/// low-score line is tried with every route having a `score_thresh`, so it goes through up
+/// to `1 + routes` rec passes. [crate::session::RettoSession::set_rec_router] picks the
+/// model up front and recognizes every line once.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RecRouteConfig {
+    pub model: RettoWorkerModelSource,
+    pub character_source: RecCharacterDictProvider,
+    pub use_space_char: bool,
+    /// Image size `[C, H, W]` of this model, see [RecProcessorConfig::image_shape]
+    pub image_shape: [usize; 3],
+    /// See [RecProcessorConfig::infer_image_shape]
+    pub infer_image_shape: bool,
+    /// Lines whose dominant script, as read by the primary model, is one of these are
+    /// recognized again by this model. The primary model can only read scripts of its own
+    /// dictionary, lines in other scripts rely on `score_thresh`.
+    pub scripts: Vec<RecScript>,
+    /// Lines scoring below this with the primary model are also tried with this model, the
+    /// result is kept if it scores higher and is written in one of `scripts`.
+    /// Disabled when `None`.
+    pub score_thresh: Option<f32>,
+}
+
+impl RecRouteConfig {
+    /// Route to the rec model and dictionary of `language`, for the scripts of
+    /// [Language::scripts] and the lines the primary model scores below 0.8
+    #[cfg(not(target_family = "wasm"))]
+    pub fn from_language(language: Language) -> Self {
+        RecRouteConfig {
+            model: language.v4_rec_model_source(),
+            character_source: RecCharacterDictProvider::OutSide(language.v4_dict_source()),
+            use_space_char: true,
+            image_shape: [3, 48, 320],
+            infer_image_shape: false,
+            scripts: language.scripts().to_vec(),
+            score_thresh: Some(0.8),
         }
     }
 }
@@ -138,21 +602,122 @@ impl Default for RecProcessorConfig {
 #[derive(Debug)]
 pub(crate) struct RecProcessor<'p> {
     character: &'p RecCharacter,
+    decoder: &'p RecDecoder,
+    charset_mask: Option<&'p [bool]>,
     config: &'p RecProcessorConfig,
+    image_shape: [usize; 3],
 }
 
 impl<'a> RecProcessor<'a> {
-    pub fn new(config: &'a RecProcessorConfig, character: &'a RecCharacter) -> Self {
-        RecProcessor { character, config }
+    pub fn new(
+        config: &'a RecProcessorConfig,
+        character: &'a RecCharacter,
+        decoder: &'a RecDecoder,
+        charset_mask: Option<&'a [bool]>,
+        image_shape: [usize; 3],
+    ) -> Self {
+        RecProcessor {
+            character,
+            decoder,
+            charset_mask,
+            config,
+            image_shape,
+        }
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum RecWordKind {
+    /// A single Han or kana character
+    Cjk,
+    /// A run of letters of the other scripts and digits, `-` and decimal points inside the
+    /// run are kept
+    AlphaNum,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RecCharResult {
+    pub text: String,
+    pub boxes: PointBox<OrderedFloat<f32>>,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RecWordResult {
+    pub text: String,
+    pub kind: RecWordKind,
+    pub boxes: PointBox<OrderedFloat<f32>>,
+    pub char_results: Vec<RecCharResult>,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RecCharCandidate {
+    pub text: String,
+    pub score: f32,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RecCharScore {
+    /// The emitted character
+    pub text: String,
+    /// Probability of the emitted character
+    pub score: f32,
+    /// Most probable alternatives at the same position, in descending order of probability
+    pub candidates: Vec<RecCharCandidate>,
+}
+
 #[derive(Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct RecProcessorSingleResult {
     pub text: String,
     pub score: f32,
-    // TODO: word_results
+    /// One entry per character of `text`, only filled when [RecProcessorConfig::char_top_k]
+    /// or [RecProcessorConfig::corrector] is set
+    pub char_scores: Vec<RecCharScore>,
+    /// Words (and their characters) of the line with boxes in the original image,
+    /// only filled when [RecProcessorConfig::return_word_box] is enabled
+    pub word_results: Vec<RecWordResult>,
+    /// Decoded text (in visual order) before bidi reordering, correction and normalization,
+    /// only set when they changed it.
+    /// `char_scores` and `word_results` describe this text.
+    pub raw_text: Option<String>,
+    /// Dominant direction of the line
+    pub direction: RecTextDirection,
+    /// Script and probable language of the line,
+    /// only filled when [RecProcessorConfig::identify_language] is enabled
+    pub language: Option<RecLanguageTag>,
+}
+
+impl RecProcessorSingleResult {
+    /// Project the word and character boxes from the normalized crop space into `line_box`,
+    /// whose first point is where the text starts
+    pub(crate) fn project_word_boxes(&mut self, line_box: &PointBox<OrderedFloat<f32>>) {
+        let Some(proj) = Projection::from_control_points(
+            [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)],
+            line_box
+                .points()
+                .map(|p| (p.x.into_inner(), p.y.into_inner())),
+        ) else {
+            tracing::warn!("Cannot project word boxes into {:?}", line_box);
+            return;
+        };
+        let project = |b: &PointBox<OrderedFloat<f32>>| {
+            b.map_points(|p| {
+                let (x, y) = proj * (p.x.into_inner(), p.y.into_inner());
+                Point::new(OrderedFloat(x), OrderedFloat(y))
+            })
+        };
+        self.word_results.iter_mut().for_each(|word| {
+            word.boxes = project(&word.boxes);
+            word.char_results
+                .iter_mut()
+                .for_each(|c| c.boxes = project(&c.boxes));
+        });
+    }
 }
 
 #[derive(Debug)]
@@ -175,7 +740,7 @@ impl ProcessorInnerIO for RecProcessor<'_> {
     type PreProcessOutput<'ppl> = Array4<f32>;
     type PostProcessInput<'ppl> = Array3<f32>;
     type PostProcessInputExtra<'ppl> = PostProcessExtraInput;
-    type PostProcessOutput<'ppl> = Vec<(String, f32)>;
+    type PostProcessOutput<'ppl> = Vec<RecProcessorSingleResult>;
 }
 
 impl ProcessorInner for RecProcessor<'_> {
@@ -195,25 +760,100 @@ impl ProcessorInner for RecProcessor<'_> {
             max_wh_ratio,
         }: Self::PostProcessInputExtra<'a>,
     ) -> RettoResult<Self::PostProcessOutput<'a>> {
+        let mut input = input;
+        if let Some(mask) = self.charset_mask {
+            input
+                .axis_iter_mut(Axis(2))
+                .zip(mask)
+                .filter(|(_, allowed)| !**allowed)
+                .for_each(|(mut lane, _)| lane.fill(0.0));
+            // Spread the probability of the masked characters over the allowed ones,
+            // so that the scores stay comparable with unmasked runs
+            input.lanes_mut(Axis(2)).into_iter().for_each(|mut lane| {
+                let total = lane.sum();
+                if total > 0.0 {
+                    lane /= total;
+                }
+            });
+        }
         let preds_idx = input.map_axis(Axis(2), |lane| lane.argmax().unwrap());
         let preds_prob = input.map_axis(Axis(2), |lane| *lane.max().unwrap());
         Ok(self.character.decode(
+            &input,
             &preds_idx,
             &preds_prob,
             &wh_ratios,
             max_wh_ratio,
-            true,
-            false,
+            RecDecodeOptions {
+                decoder: self.decoder,
+                remove_duplicate: true,
+                return_word_box: self.config.return_word_box,
+                char_top_k: self.config.char_top_k.or(self
+                    .config
+                    .corrector
+                    .as_ref()
+                    .map(|_| RecCorrectorConfig::CHAR_TOP_K)),
+                charset_mask: self.charset_mask,
+            },
         ))
     }
 }
 
+impl RecProcessor<'_> {
+    /// Split the crops into batches, each with the wh ratio it is padded to
+    fn plan_batches(&self, images: &[ImageHelper]) -> Vec<(Vec<usize>, OrderedFloat<f32>)> {
+        let mut image_index_asc_size: Vec<usize> = (0..images.len()).collect();
+        image_index_asc_size.sort_by_key(|&i| Reverse(OrderedFloat(images[i].ori_ratio())));
+        let [_, h, w] = self.image_shape;
+        let wh_ratio = |i: usize| {
+            let (img_h, img_w) = images[i].size();
+            OrderedFloat(img_w as f32 / img_h as f32)
+        };
+        match self.config.batching {
+            RecBatchStrategy::Fixed => image_index_asc_size
+                .chunks(self.config.batch_num)
+                .map(|batch_idx| {
+                    let max_wh_ratio = batch_idx
+                        .iter()
+                        .map(|&i| wh_ratio(i))
+                        .fold(OrderedFloat(w as f32 / h as f32), max);
+                    (batch_idx.to_vec(), max_wh_ratio)
+                })
+                .collect(),
+            RecBatchStrategy::Bucketed {
+                bucket_width,
+                max_batch_width,
+            } => {
+                let bucket_width = bucket_width.max(1);
+                let mut batches: Vec<(Vec<usize>, usize)> = Vec::new();
+                for i in image_index_asc_size {
+                    let width = (h as f32 * wh_ratio(i).into_inner()).ceil() as usize;
+                    let width = max(w, width.div_ceil(bucket_width) * bucket_width);
+                    match batches.last_mut() {
+                        Some((batch_idx, batch_width))
+                            if *batch_width == width
+                                && (batch_idx.len() + 1) * width <= max_batch_width =>
+                        {
+                            batch_idx.push(i)
+                        }
+                        _ => batches.push((vec![i], width)),
+                    }
+                }
+                batches
+                    .into_iter()
+                    .map(|(batch_idx, width)| (batch_idx, OrderedFloat(width as f32 / h as f32)))
+                    .collect()
+            }
+        }
+    }
+}
+
 impl<'p> Processor for RecProcessor<'p> {
     type Config = RecProcessorConfig;
-    type ProcessInput<'pl> = &'pl Vec<ImageHelper>;
+    type ProcessInput<'pl> = &'pl [ImageHelper];
     fn process<'a, F>(
         &self,
-        images: &'a Vec<ImageHelper>,
+        images: &'a [ImageHelper],
         mut worker_fun: F,
     ) -> RettoResult<Self::FinalResult>
     where
@@ -221,29 +861,21 @@ impl<'p> Processor for RecProcessor<'p> {
     {
         let mut final_res: Vec<Option<RecProcessorSingleResult>> = Vec::with_capacity(images.len());
         final_res.resize_with(images.len(), || None);
-        let mut image_index_asc_size: Vec<usize> = (0..images.len()).collect();
-        image_index_asc_size.sort_by_key(|&i| Reverse(OrderedFloat(images[i].ori_ratio())));
-        let [_, h, w] = self.config.image_shape;
-        let mut max_wh_ratio = OrderedFloat(w as f32 / h as f32);
-        image_index_asc_size
-            .chunks(self.config.batch_num)
-            .try_for_each(|batch_idx| {
-                let mut wh_ratios = Vec::with_capacity(batch_idx.len());
-                batch_idx.iter().for_each(|&i| {
-                    let img = &images[i];
-                    let (img_h, img_w) = img.size();
-                    let wh_ratio = OrderedFloat(img_w as f32 / img_h as f32);
-                    wh_ratios.push(wh_ratio);
-                    max_wh_ratio = max(max_wh_ratio, wh_ratio);
-                });
+        self.plan_batches(images)
+            .into_iter()
+            .try_for_each(|(batch_idx, max_wh_ratio)| {
+                let wh_ratios = batch_idx
+                    .iter()
+                    .map(|&i| {
+                        let (img_h, img_w) = images[i].size();
+                        OrderedFloat(img_w as f32 / img_h as f32)
+                    })
+                    .collect();
                 let mats = batch_idx
                     .iter()
                     .map(|&i| {
                         images[i]
-                            .resize_norm_image(
-                                self.config.image_shape,
-                                Some(max_wh_ratio.into_inner()),
-                            )
+                            .resize_norm_image(self.image_shape, Some(max_wh_ratio.into_inner()))
                             .insert_axis(Axis(0))
                     })
                     .collect::<Vec<_>>();
@@ -257,10 +889,7 @@ impl<'p> Processor for RecProcessor<'p> {
                     }
                 })?;
                 batch_idx.iter().zip(post_processed).for_each(|(idx, res)| {
-                    final_res[*idx] = Some(RecProcessorSingleResult {
-                        text: res.0,
-                        score: res.1,
-                    });
+                    final_res[*idx] = Some(res);
                 });
                 Ok::<(), RettoError>(())
             })?;
@@ -269,3 +898,236 @@ impl<'p> Processor for RecProcessor<'p> {
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::worker::mock_worker::{RettoMockWorker, RettoMockWorkerConfig};
+    use crate::worker::{RettoInnerWorker, RettoWorker};
+    use rstest::*;
+
+    fn character(entries: &[&str]) -> RecCharacter {
+        RecCharacter {
+            inner: entries.iter().map(|e| e.to_string()).collect(),
+            ignored_tokens: vec![0],
+        }
+    }
+
+    fn span(b: &PointBox<OrderedFloat<f32>>) -> (f32, f32) {
+        (b.tl().x.into_inner(), b.br().x.into_inner())
+    }
+
+    #[rstest]
+    #[case("hello world", &["hello", "world"])]
+    #[case("state-of-the-art", &["state-of-the-art"])]
+    #[case("v3.5 ok.", &["v3.5", "ok"])]
+    #[case("玩原神ab", &["玩", "原", "神", "ab"])]
+    #[case("東京タワーへ", &["東", "京", "タ", "ワ", "ー", "へ"])]
+    #[case("안녕 하세요", &["안녕", "하세요"])]
+    #[case("㐀ＡＢ１", &["㐀", "ＡＢ１"])]
+    fn test_word_grouping(#[case] text: &str, #[case] expected: &[&str]) {
+        let chars = text.chars().map(|c| c.to_string()).collect::<Vec<_>>();
+        let chars = chars
+            .iter()
+            .enumerate()
+            .map(|(i, c)| (c.as_str(), 0.9, 2 * i))
+            .collect::<Vec<_>>();
+        let words = get_word_results(&chars, 2.0 * chars.len() as f32);
+        assert!(
+            words
+                .iter()
+                .map(|w| w.text.as_str())
+                .eq(expected.iter().copied())
+        );
+    }
+
+    #[test]
+    fn test_word_boxes() {
+        let chars = [
+            ("a", 0.9, 0),
+            ("b", 0.9, 2),
+            (" ", 0.9, 4),
+            ("玩", 0.9, 6),
+            ("的", 0.9, 8),
+        ];
+        let words = get_word_results(&chars, 10.0);
+        let eq = |(a0, a1): (f32, f32), (b0, b1): (f32, f32)| {
+            (a0 - b0).abs() < 1e-6 && (a1 - b1).abs() < 1e-6
+        };
+        assert_eq!(words.len(), 3);
+        // alphanumeric characters are split halfway between their timesteps
+        assert_eq!(words[0].kind, RecWordKind::AlphaNum);
+        assert!(eq(span(&words[0].boxes), (0.0, 0.3)));
+        let chars = &words[0].char_results;
+        assert!(eq(span(&chars[0].boxes), (0.0, 0.15)));
+        assert!(eq(span(&chars[1].boxes), (0.15, 0.3)));
+        // CJK characters are centered on their timestep, as wide as their average spacing
+        assert_eq!(words[1].kind, RecWordKind::Cjk);
+        assert!(eq(span(&words[1].boxes), (0.5, 0.8)));
+        assert!(eq(span(&words[2].boxes), (0.7, 1.0)));
+    }
+
+    #[rstest]
+    #[case(2, &["c", "b"])]
+    #[case(10, &["c", "b", " "])]
+    #[case(0, &[])]
+    fn test_top_k_candidates(#[case] k: usize, #[case] expected: &[&str]) {
+        let character = character(&["blank", "a", "b", "c", " "]);
+        let probs = arr1(&[0.3, 0.4, 0.1, 0.15, 0.05]);
+        let candidates = character.top_k_candidates(probs.view(), 1, k, None);
+        // the blank and the emitted character are never candidates
+        assert!(
+            candidates
+                .iter()
+                .map(|c| c.text.as_str())
+                .eq(expected.iter().copied())
+        );
+        assert!(candidates.windows(2).all(|w| w[0].score >= w[1].score));
+    }
+
+    #[test]
+    fn test_charset_mask() {
+        let character = character(&["blank", "a", "b", "c"]);
+        let mask = character
+            .charset_mask(&RecCharset::Allow("ac".to_string()))
+            .unwrap();
+        assert_eq!(mask, [true, true, false, true]);
+        let config = RecProcessorConfig {
+            char_top_k: Some(3),
+            ..Default::default()
+        };
+        let processor = RecProcessor::new(
+            &config,
+            &character,
+            &RecDecoder::Greedy,
+            Some(&mask),
+            RecProcessorConfig::default().image_shape,
+        );
+        let preds = ndarray::arr3(&[[[0.1, 0.2, 0.6, 0.1], [0.9, 0.05, 0.0, 0.05]]]);
+        let res = processor
+            .postprocess(
+                preds,
+                PostProcessExtraInput {
+                    wh_ratios: vec![OrderedFloat(1.0)],
+                    max_wh_ratio: OrderedFloat(1.0),
+                },
+            )
+            .unwrap();
+        // `b` is dropped and its probability is spread over the allowed classes
+        assert_eq!(res[0].text, "a");
+        assert!((res[0].score - 0.5).abs() < 1e-6);
+        let candidates = &res[0].char_scores[0].candidates;
+        assert!(candidates.iter().map(|c| c.text.as_str()).eq(["c"]));
+        assert!((candidates[0].score - 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_dict_lines_are_trimmed() {
+        let worker = RettoMockWorker::new(RettoMockWorkerConfig::default()).unwrap();
+        let character = RecCharacter::new(
+            RecCharacterDictProvider::OutSide(RettoWorkerModelSource::Blob(
+                b"a \r\n\tb\n".to_vec(),
+            )),
+            vec![0],
+            true,
+            &worker,
+            0,
+        )
+        .unwrap();
+        assert_eq!(character.dict(), ["blank", "a", "b", " "]);
+    }
+
+    #[rstest]
+    #[case(false, &["blank", "a", "b"])]
+    #[case(true, &["blank", "a", "b", " "])]
+    fn test_inline_dict(#[case] use_space_char: bool, #[case] expected: &[&str]) {
+        let worker = RettoMockWorker::new(RettoMockWorkerConfig::default().with_line(
+            [0.1, 0.1, 0.5, 0.2],
+            "ba a",
+            false,
+        ))
+        .unwrap();
+        let character = RecCharacter::new(
+            RecCharacterDictProvider::Inline(),
+            vec![0],
+            use_space_char,
+            &worker,
+            0,
+        )
+        .unwrap();
+        assert_eq!(character.dict(), expected);
+    }
+
+    #[rstest]
+    #[case::repeats("hello")]
+    #[case::spaces("a  b c")]
+    fn test_rec_decode(#[case] text: &str) {
+        let lines = RettoMockWorkerConfig::default().with_line([0.1, 0.1, 0.9, 0.2], text, false);
+        let crops = [lines.line_crop(0, 400, 40)];
+        let mut worker = RettoMockWorker::new(lines).unwrap();
+        let config = RettoMockWorkerConfig::rec_processor_config();
+        let character = RecCharacter::new(
+            config.character_source.clone(),
+            vec![0],
+            config.use_space_char,
+            &worker,
+            0,
+        )
+        .unwrap();
+        let decoder = RecDecoder::new(&config.decoder, character.dict()).unwrap();
+        let rec = RecProcessor::new(&config, &character, &decoder, None, config.image_shape);
+        let res = rec.process(&crops, |i| worker.rec(0, i)).unwrap();
+        assert_eq!(res.0[0].text, text);
+        assert!((res.0[0].score - RettoMockWorkerConfig::CHAR_PROB).abs() < 1e-6);
+    }
+
+    /// Crops 10 pixels high with the given widths
+    fn crops(widths: &[u32]) -> Vec<ImageHelper> {
+        widths
+            .iter()
+            .map(|&w| ImageHelper::new_from_rgb_image(image::RgbImage::new(w, 10)))
+            .collect()
+    }
+
+    #[rstest]
+    #[case::fixed(
+        RecBatchStrategy::Fixed,
+        &[10, 40, 20, 100],
+        &[(vec![0, 2], 320.0 / 48.0), (vec![1, 3], 10.0)],
+    )]
+    #[case::bucketed(
+        RecBatchStrategy::Bucketed { bucket_width: 64, max_batch_width: 900 },
+        &[90, 10, 80, 20, 81, 40],
+        &[
+            (vec![1, 3], 320.0 / 48.0),
+            (vec![5], 320.0 / 48.0),
+            (vec![2], 384.0 / 48.0),
+            (vec![4, 0], 448.0 / 48.0),
+        ],
+    )]
+    fn test_plan_batches(
+        #[case] batching: RecBatchStrategy,
+        #[case] widths: &[u32],
+        #[case] expected: &[(Vec<usize>, f32)],
+    ) {
+        let character = character(&["blank", "a"]);
+        let config = RecProcessorConfig {
+            batch_num: 2,
+            batching,
+            ..Default::default()
+        };
+        let processor = RecProcessor::new(
+            &config,
+            &character,
+            &RecDecoder::Greedy,
+            None,
+            config.image_shape,
+        );
+        let batches = processor.plan_batches(&crops(widths));
+        assert_eq!(batches.len(), expected.len());
+        for ((batch, ratio), (expected_batch, expected_ratio)) in batches.iter().zip(expected) {
+            assert_eq!(batch, expected_batch);
+            assert!((ratio.into_inner() - expected_ratio).abs() < 1e-5);
+        }
+    }
+}
@@ -0,0 +1,248 @@
+use crate::serde::*;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum RecScript {
+    Han,
+    Hiragana,
+    Katakana,
+    Hangul,
+    Latin,
+    Greek,
+    Cyrillic,
+    Hebrew,
+    Arabic,
+    Devanagari,
+    Tamil,
+    Thai,
+}
+
+impl RecScript {
+    /// Script of `c`, `None` for digits, punctuation, spaces and unsupported scripts
+    pub fn of(c: char) -> Option<Self> {
+        let script = match c as u32 {
+            0x41..=0x5A | 0x61..=0x7A => RecScript::Latin,
+            0xC0..=0x24F if c != '×' && c != '÷' => RecScript::Latin,
+            0x1E00..=0x1EFF | 0xFF21..=0xFF3A | 0xFF41..=0xFF5A => RecScript::Latin,
+            0x370..=0x3FF | 0x1F00..=0x1FFF => RecScript::Greek,
+            0x400..=0x52F => RecScript::Cyrillic,
+            0x591..=0x5F4 => RecScript::Hebrew,
+            0x600..=0x6FF | 0x750..=0x77F | 0x8A0..=0x8FF => RecScript::Arabic,
+            0xFB50..=0xFDFF | 0xFE70..=0xFEFF => RecScript::Arabic,
+            0x900..=0x97F => RecScript::Devanagari,
+            0xB80..=0xBFF => RecScript::Tamil,
+            0xE00..=0xE7F => RecScript::Thai,
+            0x1100..=0x11FF | 0x3130..=0x318F | 0xAC00..=0xD7AF => RecScript::Hangul,
+            0x3041..=0x309F => RecScript::Hiragana,
+            0x30A1..=0x30FA | 0x30FD..=0x30FF | 0x31F0..=0x31FF | 0xFF66..=0xFF9D => {
+                RecScript::Katakana
+            }
+            0x2E80..=0x2FDF | 0x3005 | 0x3007 | 0x3021..=0x3029 => RecScript::Han,
+            0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xF900..=0xFAFF | 0x20000..=0x3134F => {
+                RecScript::Han
+            }
+            _ => return None,
+        };
+        Some(script)
+    }
+
+    /// ISO 639-1 code of the most common language written in this script
+    pub fn default_language(&self) -> &'static str {
+        match self {
+            RecScript::Han => "zh",
+            RecScript::Hiragana | RecScript::Katakana => "ja",
+            RecScript::Hangul => "ko",
+            RecScript::Latin => "en",
+            RecScript::Greek => "el",
+            RecScript::Cyrillic => "ru",
+            RecScript::Hebrew => "he",
+            RecScript::Arabic => "ar",
+            RecScript::Devanagari => "hi",
+            RecScript::Tamil => "ta",
+            RecScript::Thai => "th",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RecLanguageTag {
+    /// Script of most of the letters of the line
+    pub script: RecScript,
+    /// Share of the letters of the line written in `script`
+    pub script_ratio: f32,
+    /// ISO 639-1 code of the probable language
+    pub language: String,
+    /// Confidence of `language`, `script_ratio` when it is inferred from the script alone
+    pub language_score: f32,
+}
+
+impl RecLanguageTag {
+    /// Tag `text` from the script statistics of its characters, `None` if it has no letters
+    pub fn from_scripts(text: &str) -> Option<Self> {
+        let mut counts: HashMap<RecScript, usize> = HashMap::new();
+        text.chars()
+            .filter_map(RecScript::of)
+            .for_each(|script| *counts.entry(script).or_default() += 1);
+        let total = counts.values().sum::<usize>();
+        let count = |script| counts.get(&script).copied().unwrap_or(0);
+        let kana = count(RecScript::Hiragana) + count(RecScript::Katakana);
+        let (&script, &script_count) = counts
+            .iter()
+            .max_by_key(|&(script, count)| (*count, Reverse(*script as u8)))?;
+        // Japanese mixes kanji and kana, any kana is a strong hint
+        let (language, language_count) = match script {
+            RecScript::Han | RecScript::Hiragana | RecScript::Katakana if kana > 0 => {
+                ("ja", kana + count(RecScript::Han))
+            }
+            _ => (script.default_language(), script_count),
+        };
+        Some(RecLanguageTag {
+            script,
+            script_ratio: script_count as f32 / total as f32,
+            language: language.to_string(),
+            language_score: language_count as f32 / total as f32,
+        })
+    }
+
+    /// [RecLanguageTag::from_scripts], with the language refined by `classifier`
+    pub fn identify(text: &str, classifier: Option<&dyn RecLanguageClassifier>) -> Option<Self> {
+        let mut tag = Self::from_scripts(text)?;
+        if let Some((language, score)) = classifier.and_then(|c| c.classify(text, tag.script)) {
+            tag.language = language;
+            tag.language_score = score;
+        }
+        Some(tag)
+    }
+}
+
+/// Refines the language of a line whose script is shared by several languages
+pub trait RecLanguageClassifier: Debug + Send + Sync {
+    /// ISO 639-1 code and confidence of the language of `text`, `None` to keep the
+    /// language inferred from `script`
+    fn classify(&self, text: &str, script: RecScript) -> Option<(String, f32)>;
+}
+
+/// Character n-gram classifier comparing ranked n-gram profiles (Cavnar & Trenkle)
+#[derive(Debug, Default)]
+pub struct NgramRecLanguageClassifier {
+    profiles: Vec<(String, RecScript, HashMap<String, usize>)>,
+}
+
+impl NgramRecLanguageClassifier {
+    const MAX_N: usize = 3;
+    const PROFILE_LEN: usize = 300;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Learn the profile of `language` from a sample text, the script of the profile is
+    /// the dominant script of the sample
+    pub fn with_profile(mut self, language: impl Into<String>, sample: &str) -> Self {
+        if let Some(tag) = RecLanguageTag::from_scripts(sample) {
+            self.profiles
+                .push((language.into(), tag.script, Self::profile(sample)));
+        }
+        self
+    }
+
+    /// n-grams of `text` ranked by frequency
+    fn profile(text: &str) -> HashMap<String, usize> {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        text.split(|c: char| !c.is_alphabetic())
+            .filter(|w| !w.is_empty())
+            .for_each(|word| {
+                let chars = format!("_{}_", word.to_lowercase())
+                    .chars()
+                    .collect::<Vec<_>>();
+                (1..=Self::MAX_N).for_each(|n| {
+                    chars.windows(n).for_each(|gram| {
+                        *counts.entry(gram.iter().collect()).or_default() += 1;
+                    })
+                });
+            });
+        let mut ranked = counts.into_iter().collect::<Vec<_>>();
+        ranked.sort_by(|(a, ca), (b, cb)| cb.cmp(ca).then_with(|| a.cmp(b)));
+        ranked
+            .into_iter()
+            .take(Self::PROFILE_LEN)
+            .enumerate()
+            .map(|(rank, (gram, _))| (gram, rank))
+            .collect()
+    }
+}
+
+impl RecLanguageClassifier for NgramRecLanguageClassifier {
+    fn classify(&self, text: &str, script: RecScript) -> Option<(String, f32)> {
+        let doc = Self::profile(text);
+        if doc.is_empty() {
+            return None;
+        }
+        let max_distance = doc.len() * Self::PROFILE_LEN;
+        let mut distances = self
+            .profiles
+            .iter()
+            .filter(|(_, s, _)| *s == script)
+            .map(|(language, _, profile)| {
+                let distance = doc
+                    .iter()
+                    .map(|(gram, &rank)| {
+                        profile
+                            .get(gram)
+                            .map_or(Self::PROFILE_LEN, |&r| r.abs_diff(rank))
+                    })
+                    .sum::<usize>();
+                (language, distance)
+            })
+            .collect::<Vec<_>>();
+        distances.sort_by_key(|&(_, d)| d);
+        let (language, best) = distances.first()?;
+        // Confidence from the margin to the runner-up
+        let runner_up = distances.get(1).map_or(max_distance, |&(_, d)| d);
+        let score = (runner_up - best) as f32 / max_distance as f32;
+        Some((language.to_string(), (0.5 + score).min(1.0)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::*;
+
+    #[rstest]
+    #[case("玩原神玩的", RecScript::Han, "zh")]
+    #[case("東京タワー", RecScript::Han, "ja")]
+    #[case("ひらがなと漢字", RecScript::Hiragana, "ja")]
+    #[case("서울 2024", RecScript::Hangul, "ko")]
+    #[case("Привет, мир", RecScript::Cyrillic, "ru")]
+    fn test_from_scripts(#[case] text: &str, #[case] script: RecScript, #[case] language: &str) {
+        let tag = RecLanguageTag::from_scripts(text).unwrap();
+        assert_eq!((tag.script, tag.language.as_str()), (script, language));
+    }
+
+    #[test]
+    fn test_ngram_classifier() {
+        let classifier = NgramRecLanguageClassifier::new()
+            .with_profile(
+                "en",
+                "the quick brown fox jumps over the lazy dog and then the other one",
+            )
+            .with_profile(
+                "de",
+                "der schnelle braune fuchs springt über den faulen hund und dann der andere",
+            );
+        let (language, _) = classifier
+            .classify("der hund und der fuchs", RecScript::Latin)
+            .unwrap();
+        assert_eq!(language, "de");
+        assert!(
+            classifier
+                .classify("der hund", RecScript::Cyrillic)
+                .is_none()
+        );
+    }
+}
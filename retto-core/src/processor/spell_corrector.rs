@@ -0,0 +1,247 @@
+use crate::error::RettoResult;
+use crate::processor::rec_processor::{RecCharScore, RecProcessorSingleResult};
+use crate::serde::*;
+use crate::worker::{RettoWorkerModelResolvedSource, RettoWorkerModelSource};
+use std::collections::HashSet;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum RecCorrectionMode {
+    #[default]
+    /// The whole line is snapped to a vocabulary entry
+    Line,
+    /// Every whitespace-separated token is snapped on its own
+    Token,
+}
+
+/// The edit costs are weighted by [RecProcessorSingleResult::char_scores], enabling the
+/// corrector fills them with [RecCorrectorConfig::CHAR_TOP_K] alternatives per character
+/// when [crate::processor::rec_processor::RecProcessorConfig::char_top_k] is not set
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RecCorrectorConfig {
+    /// Closed vocabulary (SKUs, brand names, ...), one entry per line
+    pub vocabulary: RettoWorkerModelSource,
+    pub mode: RecCorrectionMode,
+    /// Cost of inserting a character the recognizer missed. Deleting or substituting a
+    /// recognized character costs its confidence, less the confidence of the substitute
+    /// when it is one of the top-k alternatives.
+    pub insert_cost: f32,
+    /// A correction is only applied when its cost does not exceed this ratio of the
+    /// length of the vocabulary entry
+    pub max_cost_ratio: f32,
+}
+
+impl RecCorrectorConfig {
+    /// Alternatives kept per character for the corrector when `char_top_k` is not set
+    pub const CHAR_TOP_K: usize = 3;
+
+    pub fn new(vocabulary: RettoWorkerModelSource) -> Self {
+        RecCorrectorConfig {
+            vocabulary,
+            mode: RecCorrectionMode::default(),
+            insert_cost: 1.0,
+            max_cost_ratio: 0.34,
+        }
+    }
+}
+
+/// Recognized character with the costs of editing it
+struct WeightedChar<'c> {
+    ch: char,
+    confidence: f32,
+    candidates: Option<&'c RecCharScore>,
+}
+
+impl WeightedChar<'_> {
+    fn substitute_cost(&self, other: char) -> f32 {
+        if self.ch == other {
+            return 0.0;
+        }
+        let alternative = self
+            .candidates
+            .and_then(|s| s.candidates.iter().find(|c| c.text.chars().eq([other])))
+            .map_or(0.0, |c| c.score);
+        (self.confidence - alternative).max(0.0)
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct RecCorrector {
+    config: RecCorrectorConfig,
+    entries: Vec<Vec<char>>,
+    exact: HashSet<String>,
+}
+
+impl RecCorrector {
+    pub(crate) fn new(config: &RecCorrectorConfig) -> RettoResult<Self> {
+        let content = match config.vocabulary.clone().resolve()? {
+            #[cfg(not(target_family = "wasm"))]
+            RettoWorkerModelResolvedSource::Path(path) => std::fs::read_to_string(path)?,
+            RettoWorkerModelResolvedSource::Blob(blob) => String::from_utf8(blob)?,
+        };
+        let exact = content
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .map(str::to_owned)
+            .collect::<HashSet<_>>();
+        let mut entries = exact
+            .iter()
+            .map(|e| e.chars().collect())
+            .collect::<Vec<_>>();
+        // Deterministic tie-breaking
+        entries.sort();
+        Ok(RecCorrector {
+            config: config.clone(),
+            entries,
+            exact,
+        })
+    }
+
+    /// Corrected text of `res`, the character confidences come from
+    /// [RecProcessorSingleResult::char_scores] when they still describe the text,
+    /// the line score otherwise
+    pub(crate) fn correct(&self, res: &RecProcessorSingleResult) -> String {
+        let aligned = res.raw_text.is_none() && res.char_scores.len() == res.text.chars().count();
+        let chars = res
+            .text
+            .chars()
+            .enumerate()
+            .map(|(i, ch)| {
+                let candidates = aligned.then(|| &res.char_scores[i]);
+                WeightedChar {
+                    ch,
+                    confidence: candidates.map_or(res.score, |s| s.score),
+                    candidates,
+                }
+            })
+            .collect::<Vec<_>>();
+        match self.config.mode {
+            RecCorrectionMode::Line => self.correct_segment(&chars),
+            RecCorrectionMode::Token => {
+                let mut text = String::with_capacity(res.text.len());
+                let mut rest = chars.as_slice();
+                while !rest.is_empty() {
+                    let is_space = rest[0].ch.is_whitespace();
+                    let len = rest
+                        .iter()
+                        .position(|c| c.ch.is_whitespace() != is_space)
+                        .unwrap_or(rest.len());
+                    let (segment, tail) = rest.split_at(len);
+                    match is_space {
+                        true => text.extend(segment.iter().map(|c| c.ch)),
+                        false => text.push_str(&self.correct_segment(segment)),
+                    }
+                    rest = tail;
+                }
+                text
+            }
+        }
+    }
+
+    fn correct_segment(&self, chars: &[WeightedChar]) -> String {
+        let text = chars.iter().map(|c| c.ch).collect::<String>();
+        if chars.is_empty() || self.exact.contains(&text) {
+            return text;
+        }
+        self.entries
+            .iter()
+            .filter_map(|entry| {
+                let limit = self.config.max_cost_ratio * entry.len() as f32;
+                self.edit_cost(chars, entry, limit)
+                    .map(|cost| (cost, entry))
+            })
+            .min_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map_or(text, |(_, entry)| entry.iter().collect())
+    }
+
+    /// Weighted Levenshtein distance, `None` once it exceeds `limit`
+    fn edit_cost(&self, chars: &[WeightedChar], entry: &[char], limit: f32) -> Option<f32> {
+        let insert_cost = self.config.insert_cost;
+        let mut prev = (0..=entry.len())
+            .map(|j| j as f32 * insert_cost)
+            .collect::<Vec<_>>();
+        let mut cur = vec![0.0; entry.len() + 1];
+        for c in chars {
+            cur[0] = prev[0] + c.confidence;
+            for (j, &e) in entry.iter().enumerate() {
+                cur[j + 1] = (prev[j] + c.substitute_cost(e))
+                    .min(prev[j + 1] + c.confidence)
+                    .min(cur[j] + insert_cost);
+            }
+            if cur.iter().all(|&v| v > limit) {
+                return None;
+            }
+            std::mem::swap(&mut prev, &mut cur);
+        }
+        Some(prev[entry.len()]).filter(|&cost| cost <= limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processor::bidi::RecTextDirection;
+    use crate::processor::rec_processor::RecCharCandidate;
+    use rstest::*;
+
+    #[fixture]
+    fn corrector() -> RecCorrector {
+        let mut config = RecCorrectorConfig::new(RettoWorkerModelSource::Blob(
+            b"SKU-1042\nSKU-1047\nRetto\n".to_vec(),
+        ));
+        config.mode = RecCorrectionMode::Token;
+        RecCorrector::new(&config).unwrap()
+    }
+
+    fn line(text: &str, scores: &[f32]) -> RecProcessorSingleResult {
+        RecProcessorSingleResult {
+            text: text.to_string(),
+            score: 0.9,
+            char_scores: text
+                .chars()
+                .zip(scores)
+                .map(|(c, &score)| RecCharScore {
+                    text: c.to_string(),
+                    score,
+                    candidates: vec![],
+                })
+                .collect(),
+            word_results: vec![],
+            raw_text: None,
+            direction: RecTextDirection::LeftToRight,
+            language: None,
+        }
+    }
+
+    #[rstest]
+    // The uncertain character is the cheap one to change
+    #[case("Rett0 SKU-1O47", &[0.9, 0.9, 0.9, 0.9, 0.3, 0.0, 0.9, 0.9, 0.9, 0.9, 0.9, 0.4, 0.9, 0.9], "Retto SKU-1047")]
+    // Changing confident characters is too expensive
+    #[case("Hello", &[0.99; 5], "Hello")]
+    fn test_correct_tokens(
+        corrector: RecCorrector,
+        #[case] text: &str,
+        #[case] scores: &[f32],
+        #[case] expected: &str,
+    ) {
+        assert_eq!(corrector.correct(&line(text, scores)), expected);
+    }
+
+    #[rstest]
+    fn test_candidates_break_ties(corrector: RecCorrector) {
+        let mut res = line("SKU-104?", &[0.9, 0.9, 0.9, 0.9, 0.9, 0.9, 0.9, 0.5]);
+        res.char_scores[7].candidates = vec![
+            RecCharCandidate {
+                text: "7".to_string(),
+                score: 0.4,
+            },
+            RecCharCandidate {
+                text: "2".to_string(),
+                score: 0.1,
+            },
+        ];
+        assert_eq!(corrector.correct(&res), "SKU-1047");
+    }
+}
@@ -0,0 +1,91 @@
+use crate::serde::*;
+use std::fmt::Debug;
+use unicode_normalization::UnicodeNormalization;
+
+/// Hook applied to the text of every recognized line, after decoding
+pub trait TextPostProcessor: Debug + Send + Sync {
+    fn post_process(&self, text: String) -> String;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum RecTextNormalization {
+    /// Unicode NFKC, also folds full-width ASCII and the ideographic space to half-width
+    Nfkc,
+    /// Only fold full-width ASCII (`！` to `～`) and the ideographic space to half-width
+    HalfWidth,
+    /// Trim and collapse runs of whitespace into a single space
+    CollapseWhitespace,
+    /// Replace every occurrence of each `(from, to)` pair, in order
+    Replace(Vec<(String, String)>),
+}
+
+impl TextPostProcessor for RecTextNormalization {
+    fn post_process(&self, text: String) -> String {
+        match self {
+            RecTextNormalization::Nfkc => text.nfkc().collect(),
+            RecTextNormalization::HalfWidth => text
+                .chars()
+                .map(|c| match c {
+                    '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+                    '\u{3000}' => ' ',
+                    _ => c,
+                })
+                .collect(),
+            RecTextNormalization::CollapseWhitespace => {
+                text.split_whitespace().collect::<Vec<_>>().join(" ")
+            }
+            RecTextNormalization::Replace(table) => table
+                .iter()
+                .filter(|(from, _)| !from.is_empty())
+                .fold(text, |text, (from, to)| text.replace(from, to)),
+        }
+    }
+}
+
+/// Built-in normalizations of the config followed by the user hooks
+#[derive(Debug, Default)]
+pub(crate) struct TextPostProcessChain(Vec<Box<dyn TextPostProcessor>>);
+
+impl TextPostProcessChain {
+    pub(crate) fn new(normalizations: &[RecTextNormalization]) -> Self {
+        Self(
+            normalizations
+                .iter()
+                .cloned()
+                .map(|n| Box::new(n) as Box<dyn TextPostProcessor>)
+                .collect(),
+        )
+    }
+
+    pub(crate) fn push(&mut self, processor: Box<dyn TextPostProcessor>) {
+        self.0.push(processor);
+    }
+
+    pub(crate) fn apply(&self, text: String) -> String {
+        self.0.iter().fold(text, |text, p| p.post_process(text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::*;
+
+    #[rstest]
+    #[case(RecTextNormalization::Nfkc, "ＡＢＣ，１２３！", "ABC,123!")]
+    #[case(RecTextNormalization::HalfWidth, "玩原神（ｖ５）　好", "玩原神(v5) 好")]
+    #[case(RecTextNormalization::CollapseWhitespace, "  a \t b  c ", "a b c")]
+    #[case(
+        RecTextNormalization::Replace(vec![("0O".into(), "00".into()), ("。".into(), ".".into())]),
+        "A0O1。",
+        "A001."
+    )]
+    fn test_normalization(
+        #[case] normalization: RecTextNormalization,
+        #[case] input: &str,
+        #[case] expected: &str,
+    ) {
+        assert_eq!(normalization.post_process(input.to_string()), expected);
+    }
+}
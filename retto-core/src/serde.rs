@@ -1,10 +1,12 @@
 #[cfg(feature = "serde")]
 pub use serde::{Deserialize, Serialize};
+/// `Serialize + Deserialize` when the `serde` feature is enabled, nothing otherwise
 #[cfg(feature = "serde")]
-pub(crate) trait MaybeSerde: Serialize + for<'a> Deserialize<'a> {}
+pub trait MaybeSerde: Serialize + for<'a> Deserialize<'a> {}
 #[cfg(feature = "serde")]
 impl<T> MaybeSerde for T where T: crate::serde::Serialize + for<'de> crate::serde::Deserialize<'de> {}
+/// `Serialize + Deserialize` when the `serde` feature is enabled, nothing otherwise
 #[cfg(not(feature = "serde"))]
-pub(crate) trait MaybeSerde {}
+pub trait MaybeSerde {}
 #[cfg(not(feature = "serde"))]
 impl<T> MaybeSerde for T {}
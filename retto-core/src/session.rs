@@ -1,17 +1,99 @@
-use crate::error::RettoResult;
+use crate::error::{RettoError, RettoResult};
 use crate::image_helper::ImageHelper;
+use crate::points::PointBox;
+use crate::processor::bidi;
 use crate::processor::prelude::*;
+use crate::processor::spell_corrector::RecCorrector;
+use crate::processor::text_post_processor::TextPostProcessChain;
 use crate::serde::*;
 use crate::worker::RettoWorker;
+use image::RgbImage;
+use ordered_float::OrderedFloat;
+use std::fmt::Debug;
 use std::sync::mpsc;
 
 #[derive(Debug)]
 pub struct RettoSession<W: RettoWorker> {
     worker: W,
-    rec_character: RecCharacter,
+    /// The primary rec model, then one per [RettoSessionConfig::rec_routes]
+    rec_models: Vec<RecModel>,
+    rec_router: Option<Box<dyn RecRouter>>,
+    rec_corrector: Option<RecCorrector>,
+    text_post_processors: TextPostProcessChain,
+    rec_language_classifier: Option<Box<dyn RecLanguageClassifier>>,
+    cls_image_shape: [usize; 3],
     config: RettoSessionConfig<W>,
 }
 
+/// Rec model of the session with its dictionary and decoder
+#[derive(Debug)]
+struct RecModel {
+    character: RecCharacter,
+    decoder: RecDecoder,
+    image_shape: [usize; 3],
+}
+
+impl RecModel {
+    fn new<W: RettoWorker>(
+        worker: &W,
+        model: usize,
+        stage: &str,
+        character: RecCharacter,
+        image_shape: [usize; 3],
+        infer_image_shape: bool,
+        decoder: RecDecoder,
+    ) -> RettoResult<Self> {
+        let rec_io = worker.rec_io(model);
+        rec_io.check_rank(stage, 4, 3)?;
+        rec_io.check_output_dim(stage, 2, character.dict().len(), "classes")?;
+        let image_shape = rec_io.resolve_image_shape(stage, image_shape, infer_image_shape)?;
+        Ok(RecModel {
+            character,
+            decoder,
+            image_shape,
+        })
+    }
+}
+
+/// Lines of a run, as needed to recognize the low-score ones again
+#[derive(Clone, Copy)]
+struct RetryInput<'r> {
+    /// The resized image the crops were taken from
+    image: &'r ImageHelper,
+    /// `(height, width)` of the original image
+    ori_size: (usize, usize),
+    /// Detected boxes in the resized image
+    crop_boxes: &'r [PointBox<OrderedFloat<f32>>],
+    /// Boxes in the original image, starting where the text starts
+    line_boxes: &'r [PointBox<OrderedFloat<f32>>],
+    crop_images: &'r [ImageHelper],
+    flipped: &'r [bool],
+    /// Rec model of every line
+    models: &'r [usize],
+}
+
+/// Picks the rec model of every line crop, see [RettoSession::set_rec_router]
+pub trait RecRouter: Send + Sync {
+    /// Index in [RettoSessionConfig::rec_routes] of the model recognizing `crop`,
+    /// `None` for the primary model
+    fn route(&self, crop: &RgbImage) -> Option<usize>;
+}
+
+impl<F> RecRouter for F
+where
+    F: Fn(&RgbImage) -> Option<usize> + Send + Sync,
+{
+    fn route(&self, crop: &RgbImage) -> Option<usize> {
+        self(crop)
+    }
+}
+
+impl Debug for dyn RecRouter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("RecRouter")
+    }
+}
+
 #[derive(Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct RettoSessionConfig<W: RettoWorker> {
@@ -21,6 +103,8 @@ pub struct RettoSessionConfig<W: RettoWorker> {
     pub det_processor_config: DetProcessorConfig,
     pub cls_processor_config: ClsProcessorConfig,
     pub rec_processor_config: RecProcessorConfig,
+    /// Extra rec models for mixed-script pages, e.g. Korean captions on a Chinese page
+    pub rec_routes: Vec<RecRouteConfig>,
 }
 
 impl<W> Default for RettoSessionConfig<W>
@@ -35,10 +119,19 @@ where
             det_processor_config: DetProcessorConfig::default(),
             cls_processor_config: ClsProcessorConfig::default(),
             rec_processor_config: RecProcessorConfig::default(),
+            rec_routes: Vec::new(),
         }
     }
 }
 
+/// Per-call overrides of the session config
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RettoRunOptions {
+    /// Overrides [RecProcessorConfig::charset]
+    pub rec_charset: Option<RecCharset>,
+}
+
 #[derive(Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct RettoWorkerResult {
@@ -61,21 +154,134 @@ where
 {
     pub fn new(cfg: RettoSessionConfig<W>) -> RettoResult<Self> {
         // load dict
-        let worker = W::new(cfg.worker_config.clone())?; // TODO:
-        let rec_character =
-            RecCharacter::new(cfg.rec_processor_config.character_source.clone(), vec![0])?;
+        let mut worker = W::new(cfg.worker_config.clone())?; // TODO:
+        // Check the model signatures up front, instead of failing on the first image
+        let det_io = worker.det_io();
+        det_io.check_rank("det", 4, 4)?;
+        if det_io.input_dim(1).is_some_and(|c| c != 3) {
+            return Err(RettoError::ModelConfigMismatchError(format!(
+                "det model input is {:?}, expected 3 channels",
+                det_io.input_shape
+            )));
+        }
+        let cls_io = worker.cls_io();
+        cls_io.check_rank("cls", 4, 2)?;
+        cls_io.check_output_dim("cls", 1, cfg.cls_processor_config.label.len(), "classes")?;
+        let cls_image_shape = cls_io.resolve_image_shape(
+            "cls",
+            cfg.cls_processor_config.image_shape,
+            cfg.cls_processor_config.infer_image_shape,
+        )?;
+        let rec_config = &cfg.rec_processor_config;
+        let character = RecCharacter::new(
+            rec_config.character_source.clone(),
+            vec![0],
+            rec_config.use_space_char,
+            &worker,
+            0,
+        )?;
+        let decoder = RecDecoder::new(&rec_config.decoder, character.dict())?;
+        let mut rec_models = vec![RecModel::new(
+            &worker,
+            0,
+            "rec",
+            character,
+            rec_config.image_shape,
+            rec_config.infer_image_shape,
+            decoder,
+        )?];
+        for (i, route) in cfg.rec_routes.iter().enumerate() {
+            let model = worker.add_rec_model(route.model.clone())?;
+            let character = RecCharacter::new(
+                route.character_source.clone(),
+                vec![0],
+                route.use_space_char,
+                &worker,
+                model,
+            )?;
+            // The lexicon and the language model are loaded once for all the rec models
+            let decoder = rec_models[0].decoder.for_dict(character.dict());
+            rec_models.push(RecModel::new(
+                &worker,
+                model,
+                &format!("rec route {i}"),
+                character,
+                route.image_shape,
+                route.infer_image_shape,
+                decoder,
+            )?);
+        }
+        tracing::debug!(
+            "cls image shape: {cls_image_shape:?}, rec image shapes: {:?}",
+            rec_models.iter().map(|m| m.image_shape).collect::<Vec<_>>()
+        );
         worker.init()?;
         Ok(RettoSession {
             worker,
-            rec_character,
+            rec_models,
+            rec_router: None,
+            rec_corrector: cfg
+                .rec_processor_config
+                .corrector
+                .as_ref()
+                .map(RecCorrector::new)
+                .transpose()?,
+            text_post_processors: TextPostProcessChain::new(
+                &cfg.rec_processor_config.text_normalization,
+            ),
+            rec_language_classifier: None,
+            cls_image_shape,
             config: cfg,
         })
     }
 
-    fn process_pipeline<F>(&mut self, input: impl AsRef<[u8]>, mut callback: F) -> RettoResult<()>
+    /// Plug a custom language model into the beam search decoder, it replaces the one
+    /// loaded from [RecBeamSearchConfig::lm]. Returns `false` (and does nothing) when the
+    /// session does not use [RecDecoderConfig::BeamSearch]. Only the primary rec model uses it.
+    pub fn set_rec_language_model(&mut self, lm: impl RecLanguageModel + 'static) -> bool {
+        self.rec_models[0].decoder.set_language_model(Box::new(lm))
+    }
+
+    /// Append a hook to the text post-processing chain, it runs after the normalizations of
+    /// [RecProcessorConfig::text_normalization] and the hooks added before
+    pub fn add_text_post_processor(&mut self, processor: impl TextPostProcessor + 'static) {
+        self.text_post_processors.push(Box::new(processor));
+    }
+
+    /// Classifier refining the language of the lines when
+    /// [RecProcessorConfig::identify_language] is enabled, e.g. [NgramRecLanguageClassifier]
+    pub fn set_rec_language_classifier(
+        &mut self,
+        classifier: impl RecLanguageClassifier + 'static,
+    ) {
+        self.rec_language_classifier = Some(Box::new(classifier));
+    }
+
+    /// Route every line crop to a rec model with `router` instead of by script, see
+    /// [RecRouteConfig::scripts]. The crops are routed before recognition, so every line is
+    /// recognized once.
+    pub fn set_rec_router(&mut self, router: impl RecRouter + 'static) {
+        self.rec_router = Some(Box::new(router));
+    }
+
+    fn process_pipeline<F>(
+        &mut self,
+        input: impl AsRef<[u8]>,
+        options: &RettoRunOptions,
+        mut callback: F,
+    ) -> RettoResult<()>
     where
         F: FnMut(RettoWorkerStageResult),
     {
+        let charset = options
+            .rec_charset
+            .as_ref()
+            .unwrap_or(&self.config.rec_processor_config.charset);
+        let charset_masks = self
+            .rec_models
+            .iter()
+            .map(|model| model.character.charset_mask(charset))
+            .collect::<Vec<_>>();
         let mut image = ImageHelper::new_from_raw_img_flow(input)?; // TODO: args
         let (ori_h, ori_w) = image.size();
         let (ratio_h, ratio_w) =
@@ -90,26 +296,322 @@ where
             .iter()
             .map(|res| ImageHelper::new_from_rgb_image(image.get_crop_img(&res.boxes)))
             .collect::<Vec<_>>();
+        // Keep the boxes in the resized coordinates, so that low-score lines can be cropped again
+        let crop_boxes = det_res
+            .0
+            .iter()
+            .map(|res| res.boxes.clone())
+            .collect::<Vec<_>>();
         // So we have to resample the point boxes (to ensure consistency of coordinates)...
         for res in &mut det_res.0 {
             res.boxes
                 .scale_and_clip(after_w as f64, after_h as f64, ori_w as f64, ori_h as f64);
         }
+        // Vertical crops are rotated by 270 degrees, so their text starts from the top-right point
+        let mut line_boxes = det_res
+            .0
+            .iter()
+            .zip(&crop_boxes)
+            .map(|(res, crop_box)| {
+                res.boxes
+                    .shift_start(ImageHelper::is_vertical_crop(crop_box) as usize)
+            })
+            .collect::<Vec<_>>();
         callback(RettoWorkerStageResult::Det(det_res));
-        let cls = ClsProcessor::new(&self.config.cls_processor_config);
+        let cls = ClsProcessor::new(&self.config.cls_processor_config, self.cls_image_shape);
         let cls_res = cls.process(&mut crop_images, |i| self.worker.cls(i))?;
+        let flipped = cls_res
+            .0
+            .iter()
+            .map(|r| {
+                r.label.label == 180 && r.label.score >= self.config.cls_processor_config.thresh
+            })
+            .collect::<Vec<_>>();
+        line_boxes
+            .iter_mut()
+            .zip(&flipped)
+            .filter(|(_, flipped)| **flipped)
+            .for_each(|(line_box, _)| *line_box = line_box.shift_start(2));
         callback(RettoWorkerStageResult::Cls(cls_res));
-        let rec = RecProcessor::new(&self.config.rec_processor_config, &self.rec_character);
-        let rec_res = rec.process(&crop_images, |i| self.worker.rec(i))?;
+        let route_count = self.config.rec_routes.len();
+        let mut models = match &self.rec_router {
+            Some(router) => crop_images
+                .iter()
+                .map(|crop| match router.route(crop.rgb_image()) {
+                    None => Ok(0),
+                    Some(route) if route < route_count => Ok(route + 1),
+                    Some(route) => Err(RettoError::ModelConfigMismatchError(format!(
+                        "rec router picked route {route}, but only {route_count} are configured"
+                    ))),
+                })
+                .collect::<RettoResult<Vec<_>>>()?,
+            None => vec![0; crop_images.len()],
+        };
+        let mut rec_res = self.recognize(&crop_images, &models, &charset_masks)?;
+        if self.rec_router.is_none() {
+            self.route_by_script(&crop_images, &mut models, &charset_masks, &mut rec_res)?;
+        }
+        if self.config.rec_processor_config.return_word_box {
+            rec_res
+                .0
+                .iter_mut()
+                .zip(&line_boxes)
+                .for_each(|(res, line_box)| res.project_word_boxes(line_box));
+        }
+        let retry_input = RetryInput {
+            image: &image,
+            ori_size: (ori_h, ori_w),
+            crop_boxes: &crop_boxes,
+            line_boxes: &line_boxes,
+            crop_images: &crop_images,
+            flipped: &flipped,
+            models: &models,
+        };
+        self.retry_low_score_lines(&retry_input, &charset_masks, &mut rec_res)?;
+        let bidi_reorder = self.config.rec_processor_config.bidi_reorder;
+        let identify_language = self.config.rec_processor_config.identify_language;
+        rec_res.0.iter_mut().for_each(|res| {
+            let raw = res.text.clone();
+            if bidi_reorder {
+                let (logical, _) = bidi::visual_to_logical(&raw);
+                if logical != raw {
+                    res.text = logical;
+                    res.raw_text = Some(raw.clone());
+                }
+            }
+            let text = match &self.rec_corrector {
+                Some(corrector) => corrector.correct(res),
+                None => res.text.clone(),
+            };
+            res.text = self.text_post_processors.apply(text);
+            res.raw_text = (res.text != raw).then_some(raw);
+            if identify_language {
+                res.language =
+                    RecLanguageTag::identify(&res.text, self.rec_language_classifier.as_deref());
+            }
+        });
         callback(RettoWorkerStageResult::Rec(rec_res));
         Ok(())
     }
 
+    /// Recognize every crop with the rec model `models[i]`, in the order of `crops`
+    fn recognize(
+        &mut self,
+        crops: &[ImageHelper],
+        models: &[usize],
+        charset_masks: &[Option<Vec<bool>>],
+    ) -> RettoResult<RecProcessorResult> {
+        let mut lines = Vec::with_capacity(crops.len());
+        lines.resize_with(crops.len(), || None);
+        for (model, rec_model) in self.rec_models.iter().enumerate() {
+            let indices = (0..crops.len())
+                .filter(|&i| models[i] == model)
+                .collect::<Vec<_>>();
+            if indices.is_empty() {
+                continue;
+            }
+            let rec = RecProcessor::new(
+                &self.config.rec_processor_config,
+                &rec_model.character,
+                &rec_model.decoder,
+                charset_masks[model].as_deref(),
+                rec_model.image_shape,
+            );
+            let worker = &mut self.worker;
+            let res = match indices.len() == crops.len() {
+                true => rec.process(crops, |i| worker.rec(model, i))?,
+                false => {
+                    let subset = indices
+                        .iter()
+                        .map(|&i| crops[i].clone())
+                        .collect::<Vec<_>>();
+                    rec.process(&subset, |i| worker.rec(model, i))?
+                }
+            };
+            indices
+                .into_iter()
+                .zip(res.0)
+                .for_each(|(i, res)| lines[i] = Some(res));
+        }
+        Ok(RecProcessorResult(
+            lines
+                .into_iter()
+                .map(|res| res.expect("every line is routed to a rec model"))
+                .collect(),
+        ))
+    }
+
+    /// Recognize again with a routed model the lines whose script, as read by the primary
+    /// model, belongs to a route, or whose score is below the threshold of a route. A script
+    /// missing from the primary dictionary is never read, so such lines are only caught by
+    /// the threshold.
+    fn route_by_script(
+        &mut self,
+        crops: &[ImageHelper],
+        models: &mut [usize],
+        charset_masks: &[Option<Vec<bool>>],
+        rec_res: &mut RecProcessorResult,
+    ) -> RettoResult<()> {
+        let routes = &self.config.rec_routes;
+        let script_of = |text: &str| RecLanguageTag::from_scripts(text).map(|tag| tag.script);
+        // (line, model, whether the script matched)
+        let candidates = rec_res
+            .0
+            .iter()
+            .enumerate()
+            .flat_map(|(i, res)| {
+                let script = script_of(&res.text);
+                match routes
+                    .iter()
+                    .position(|route| script.is_some_and(|s| route.scripts.contains(&s)))
+                {
+                    Some(route) => vec![(i, route + 1, true)],
+                    None => routes
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, route)| route.score_thresh.is_some_and(|t| res.score < t))
+                        .map(|(route, _)| (i, route + 1, false))
+                        .collect(),
+                }
+            })
+            .collect::<Vec<_>>();
+        if candidates.is_empty() {
+            return Ok(());
+        }
+        let (crops, candidate_models): (Vec<_>, Vec<_>) = candidates
+            .iter()
+            .map(|&(i, model, _)| (crops[i].clone(), model))
+            .unzip();
+        let routed = self.recognize(&crops, &candidate_models, charset_masks)?;
+        for (&(i, model, matched), res) in candidates.iter().zip(routed.0) {
+            let scripts = &self.config.rec_routes[model - 1].scripts;
+            let accepted = matched
+                || (res.score > rec_res.0[i].score
+                    && script_of(&res.text).is_some_and(|s| scripts.contains(&s)));
+            if accepted {
+                tracing::debug!(
+                    "Rec route {} took over line {}: {:?} -> {:?}",
+                    model - 1,
+                    i,
+                    rec_res.0[i],
+                    res
+                );
+                rec_res.0[i] = res;
+                models[i] = model;
+            }
+        }
+        Ok(())
+    }
+
+    fn retry_low_score_lines(
+        &mut self,
+        input: &RetryInput,
+        charset_masks: &[Option<Vec<bool>>],
+        rec_res: &mut RecProcessorResult,
+    ) -> RettoResult<()> {
+        let Some(retry) = self.config.rec_processor_config.retry.clone() else {
+            return Ok(());
+        };
+        let RetryInput {
+            image,
+            ori_size: (ori_h, ori_w),
+            crop_boxes,
+            line_boxes,
+            crop_images,
+            flipped,
+            models,
+        } = *input;
+        let return_word_box = self.config.rec_processor_config.return_word_box;
+        let (img_h, img_w) = image.size();
+        let mut budget = retry.max_attempts;
+        let mut pending = (0..rec_res.0.len())
+            .filter(|&i| rec_res.0[i].score < retry.score_thresh)
+            .collect::<Vec<_>>();
+        pending.sort_by_key(|&i| OrderedFloat(rec_res.0[i].score));
+        for strategy in &retry.strategies {
+            if pending.is_empty() || budget == 0 {
+                break;
+            }
+            pending.truncate(budget);
+            budget -= pending.len();
+            let (variants, variant_boxes): (Vec<_>, Vec<_>) = pending
+                .iter()
+                .map(|&i| {
+                    let variant = match strategy {
+                        RecRetryStrategy::Unclip(ratio) => {
+                            let point_box = crop_boxes[i].unclip_rect(
+                                *ratio as f64,
+                                img_w as f64,
+                                img_h as f64,
+                            );
+                            let mut crop =
+                                ImageHelper::new_from_rgb_image(image.get_crop_img(&point_box));
+                            if flipped[i] {
+                                crop.rotate_180_in_place()?;
+                            }
+                            let line_box = line_boxes[i].unclip_rect(
+                                *ratio as f64,
+                                ori_w as f64,
+                                ori_h as f64,
+                            );
+                            (crop, line_box)
+                        }
+                        RecRetryStrategy::Upscale(factor) => {
+                            (crop_images[i].upscaled(*factor), line_boxes[i].clone())
+                        }
+                        RecRetryStrategy::ContrastStretch => {
+                            (crop_images[i].contrast_stretched(), line_boxes[i].clone())
+                        }
+                        RecRetryStrategy::Invert => {
+                            (crop_images[i].inverted(), line_boxes[i].clone())
+                        }
+                        RecRetryStrategy::Rotate180 => (
+                            ImageHelper::new_from_rgb_image(crop_images[i].rotate_180()?),
+                            line_boxes[i].shift_start(2),
+                        ),
+                    };
+                    Ok(variant)
+                })
+                .collect::<RettoResult<Vec<_>>>()?
+                .into_iter()
+                .unzip();
+            let variant_models = pending.iter().map(|&i| models[i]).collect::<Vec<_>>();
+            let retried = self.recognize(&variants, &variant_models, charset_masks)?;
+            pending.iter().zip(retried.0).zip(&variant_boxes).for_each(
+                |((&i, mut res), line_box)| {
+                    if res.score > rec_res.0[i].score {
+                        if return_word_box {
+                            res.project_word_boxes(line_box);
+                        }
+                        tracing::debug!(
+                            "Rec retry {:?} improved line {}: {:?} -> {:?}",
+                            strategy,
+                            i,
+                            rec_res.0[i],
+                            res
+                        );
+                        rec_res.0[i] = res;
+                    }
+                },
+            );
+            pending.retain(|&i| rec_res.0[i].score < retry.score_thresh);
+        }
+        Ok(())
+    }
+
     pub fn run(&mut self, input: impl AsRef<[u8]>) -> RettoResult<RettoWorkerResult> {
+        self.run_with_options(input, &RettoRunOptions::default())
+    }
+
+    pub fn run_with_options(
+        &mut self,
+        input: impl AsRef<[u8]>,
+        options: &RettoRunOptions,
+    ) -> RettoResult<RettoWorkerResult> {
         let mut det_opt = None;
         let mut cls_opt = None;
         let mut rec_opt = None;
-        self.process_pipeline(input, |stage| match stage {
+        self.process_pipeline(input, options, |stage| match stage {
             RettoWorkerStageResult::Det(r) => {
                 tracing::debug!("Det result: {:?}", r);
                 det_opt = Some(r)
@@ -135,7 +637,16 @@ where
         input: impl AsRef<[u8]>,
         sender: mpsc::Sender<RettoWorkerStageResult>,
     ) -> RettoResult<()> {
-        self.process_pipeline(input, |stage| {
+        self.run_stream_with_options(input, &RettoRunOptions::default(), sender)
+    }
+
+    pub fn run_stream_with_options(
+        &mut self,
+        input: impl AsRef<[u8]>,
+        options: &RettoRunOptions,
+        sender: mpsc::Sender<RettoWorkerStageResult>,
+    ) -> RettoResult<()> {
+        self.process_pipeline(input, options, |stage| {
             if let Err(e) = sender.send(stage) {
                 tracing::error!("Error sending request {:?}", e);
             }
@@ -254,6 +765,61 @@ mod tests {
         Ok(())
     }
 
+    /// Run the same image through the ORT session and a session on another backend
+    fn assert_agrees_with_ort<W: RettoWorker>(
+        session: &mut RettoSession<RettoOrtWorker>,
+        other: &mut RettoSession<W>,
+    ) -> AnyResult<()> {
+        let image = draw_text(
+            &GLOBAL_FONT,
+            "玩原神玩的",
+            PxScale::from(20.0),
+            200,
+            50,
+            0,
+            0,
+        );
+        let image = rotate_text(&image, 180.0);
+        let mut buf = Vec::new();
+        image.write_to(&mut Cursor::new(&mut buf), ImageFormat::Png)?;
+        let ort_res = session.run(&buf)?;
+        let other_res = other.run(&buf)?;
+        assert_eq!(ort_res.det_result.0.len(), other_res.det_result.0.len());
+        for (ort_det, other_det) in ort_res.det_result.0.iter().zip(&other_res.det_result.0) {
+            for (lhs, rhs) in ort_det.boxes.points().iter().zip(other_det.boxes.points()) {
+                assert!(points_range(lhs, rhs.x.into_inner(), rhs.y.into_inner()) < 1f32);
+            }
+        }
+        for (ort_cls, other_cls) in ort_res.cls_result.0.iter().zip(&other_res.cls_result.0) {
+            assert_eq!(ort_cls.label.label, other_cls.label.label);
+        }
+        for (ort_rec, other_rec) in ort_res.rec_result.0.iter().zip(&other_res.rec_result.0) {
+            assert_eq!(ort_rec.text, other_rec.text);
+            assert!((ort_rec.score - other_rec.score).abs() < 1e-3);
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "backend-tract")]
+    #[rstest]
+    fn test_tract_agrees_with_ort(mut session: RettoSession<RettoOrtWorker>) -> AnyResult<()> {
+        let mut tract_session = RettoSession::new(RettoSessionConfig::<RettoTractWorker> {
+            worker_config: RettoTractWorkerConfig::default(),
+            ..Default::default()
+        })?;
+        assert_agrees_with_ort(&mut session, &mut tract_session)
+    }
+
+    #[cfg(feature = "backend-candle")]
+    #[rstest]
+    fn test_candle_agrees_with_ort(mut session: RettoSession<RettoOrtWorker>) -> AnyResult<()> {
+        let mut candle_session = RettoSession::new(RettoSessionConfig::<RettoCandleWorker> {
+            worker_config: RettoCandleWorkerConfig::default(),
+            ..Default::default()
+        })?;
+        assert_agrees_with_ort(&mut session, &mut candle_session)
+    }
+
     #[test]
     #[allow(clippy::all)]
     #[should_panic]
@@ -264,3 +830,217 @@ mod tests {
         );
     }
 }
+
+#[cfg(test)]
+mod mock_tests {
+    use crate::prelude::*;
+    use rstest::*;
+
+    fn mock_session(
+        worker_config: RettoMockWorkerConfig,
+        rec_processor_config: RecProcessorConfig,
+    ) -> RettoResult<RettoSession<RettoMockWorker>> {
+        RettoSession::new(RettoSessionConfig {
+            worker_config,
+            rec_processor_config,
+            ..Default::default()
+        })
+    }
+
+    #[rstest]
+    #[case::disabled(None, 0.6)]
+    #[case::enabled(Some(RecRetryConfig::default()), RettoMockWorkerConfig::CHAR_PROB)]
+    fn test_retry_low_score_lines(
+        #[case] retry: Option<RecRetryConfig>,
+        #[case] expected_score: f32,
+    ) -> RettoResult<()> {
+        let lines = RettoMockWorkerConfig {
+            rec_call_probs: vec![0.6],
+            ..Default::default()
+        }
+        .with_line([0.1, 0.1, 0.6, 0.2], "hello", false);
+        let image = lines.render(400, 300)?;
+        let mut session = mock_session(
+            lines,
+            RecProcessorConfig {
+                retry,
+                ..RettoMockWorkerConfig::rec_processor_config()
+            },
+        )?;
+        let res = session.run(image)?;
+        assert_eq!(res.rec_result.0[0].text, "hello");
+        assert!((res.rec_result.0[0].score - expected_score).abs() < 1e-6);
+        Ok(())
+    }
+
+    #[rstest]
+    fn test_word_boxes() -> RettoResult<()> {
+        let lines =
+            RettoMockWorkerConfig::default().with_line([0.1, 0.1, 0.6, 0.2], "hello world", false);
+        let image = lines.render(400, 300)?;
+        let mut session = mock_session(
+            lines,
+            RecProcessorConfig {
+                return_word_box: true,
+                ..RettoMockWorkerConfig::rec_processor_config()
+            },
+        )?;
+        let res = session.run(image)?;
+        let line = &res.det_result.0[0].boxes;
+        let words = &res.rec_result.0[0].word_results;
+        assert!(words.iter().map(|w| w.text.as_str()).eq(["hello", "world"]));
+        assert!(words[0].boxes.tr().x < words[1].boxes.tl().x);
+        // projected into the detected box of the line
+        for point in words.iter().flat_map(|w| w.boxes.points()) {
+            assert!(point.x >= line.tl().x - 1.0 && point.x <= line.br().x + 1.0);
+            assert!(point.y >= line.tl().y - 1.0 && point.y <= line.br().y + 1.0);
+        }
+        assert_eq!(words[0].char_results.len(), 5);
+        Ok(())
+    }
+
+    #[rstest]
+    fn test_corrector_without_char_top_k() -> RettoResult<()> {
+        // The rec model reads the unknown "x" as "e" with a low confidence
+        let lines = RettoMockWorkerConfig {
+            rec_charsets: vec!["helo".to_string()],
+            ..Default::default()
+        }
+        .with_line([0.1, 0.1, 0.6, 0.2], "hellx", false);
+        let image = lines.render(400, 300)?;
+        let mut corrector =
+            RecCorrectorConfig::new(RettoWorkerModelSource::Blob(b"hello".to_vec()));
+        // Only affordable with the confidence of the last character, not the line score
+        corrector.max_cost_ratio = 0.15;
+        let mut session = mock_session(
+            lines,
+            RecProcessorConfig {
+                corrector: Some(corrector),
+                ..RettoMockWorkerConfig::rec_processor_config()
+            },
+        )?;
+        let res = session.run(image)?;
+        let line = &res.rec_result.0[0];
+        assert_eq!(line.text, "hello");
+        assert_eq!(line.raw_text.as_deref(), Some("helle"));
+        assert_eq!(line.char_scores.len(), 5);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(false, None)]
+    #[case(true, Some([3, 48, 192]))]
+    fn test_cls_image_shape_is_validated(
+        #[case] infer_image_shape: bool,
+        #[case] expected: Option<[usize; 3]>,
+    ) {
+        let session = RettoSession::<RettoMockWorker>::new(RettoSessionConfig {
+            cls_processor_config: ClsProcessorConfig {
+                image_shape: [3, 32, 100],
+                infer_image_shape,
+                ..Default::default()
+            },
+            rec_processor_config: RettoMockWorkerConfig::rec_processor_config(),
+            ..Default::default()
+        });
+        match session {
+            Ok(session) => assert_eq!(Some(session.cls_image_shape), expected),
+            Err(RettoError::ModelConfigMismatchError(_)) => assert_eq!(expected, None),
+            Err(e) => panic!("unexpected {e:?}"),
+        }
+    }
+
+    /// Excerpt of the PP-OCRv5 dictionary: simplified and traditional Chinese, kana, pinyin
+    const V5_DICT_EXCERPT: &str = "玩原神的繁體ひらがなカタカナpīny";
+
+    #[rstest]
+    #[case::matching(V5_DICT_EXCERPT, true)]
+    #[case::missing_chars("玩原神的", false)]
+    fn test_pp_ocr_v5(#[case] dict: &str, #[case] matches: bool) -> RettoResult<()> {
+        let rec_processor_config = RecProcessorConfig::pp_ocr_v5();
+        assert!(matches!(
+            &rec_processor_config.character_source,
+            RecCharacterDictProvider::OutSide(RettoWorkerModelSource::Path(path))
+                if path == PpOcrV5Model::DICT_FILE
+        ));
+        // The dictionary file, one character per line in the order of the mock rec model
+        let dict = dict
+            .chars()
+            .collect::<std::collections::BTreeSet<_>>()
+            .into_iter()
+            .map(String::from)
+            .collect::<Vec<_>>()
+            .join("\n");
+        let lines = RettoMockWorkerConfig {
+            rec_charsets: vec![V5_DICT_EXCERPT.to_string()],
+            ..Default::default()
+        }
+        .with_line([0.1, 0.1, 0.6, 0.2], "玩原神玩的", true)
+        .with_line([0.1, 0.4, 0.6, 0.5], "繁體ひらがなカタカナ", false)
+        .with_line([0.1, 0.7, 0.6, 0.8], "pīnyīn", false);
+        let image = lines.render(400, 300)?;
+        let session = RettoSession::<RettoMockWorker>::new(RettoSessionConfig {
+            worker_config: lines,
+            det_processor_config: DetProcessorConfig::pp_ocr_v5(),
+            rec_processor_config: RecProcessorConfig {
+                character_source: RecCharacterDictProvider::OutSide(RettoWorkerModelSource::Blob(
+                    dict.into_bytes(),
+                )),
+                ..rec_processor_config
+            },
+            ..Default::default()
+        });
+        let mut session = match session {
+            Ok(session) => session,
+            // The rec IO validation rejects a dictionary of another size than the model output
+            Err(RettoError::ModelConfigMismatchError(_)) if !matches => return Ok(()),
+            Err(e) => panic!("unexpected {e:?}"),
+        };
+        assert!(matches);
+        let res = session.run(image)?;
+        assert!(res.rec_result.0.iter().map(|r| r.text.as_str()).eq([
+            "玩原神玩的",
+            "繁體ひらがなカタカナ",
+            "pīnyīn"
+        ]));
+        assert_eq!(res.cls_result.0[0].label.label, 180);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case::fallback(Some(0.8), "안녕", RettoMockWorkerConfig::CHAR_PROB)]
+    #[case::script_only(None, "ee", RettoMockWorkerConfig::UNKNOWN_PROB)]
+    fn test_route_by_script(
+        #[case] score_thresh: Option<f32>,
+        #[case] expected_text: &str,
+        #[case] expected_score: f32,
+    ) -> RettoResult<()> {
+        // The primary model knows no Hangul, the routed one knows every character
+        let lines = RettoMockWorkerConfig {
+            rec_charsets: vec!["hello".to_string()],
+            ..Default::default()
+        }
+        .with_line([0.1, 0.1, 0.6, 0.2], "hello", false)
+        .with_line([0.1, 0.5, 0.6, 0.6], "안녕", false);
+        let image = lines.render(400, 300)?;
+        let mut session = RettoSession::<RettoMockWorker>::new(RettoSessionConfig {
+            worker_config: lines,
+            rec_processor_config: RettoMockWorkerConfig::rec_processor_config(),
+            rec_routes: vec![RecRouteConfig {
+                model: RettoWorkerModelSource::Blob(Vec::new()),
+                character_source: RecCharacterDictProvider::Inline(),
+                use_space_char: true,
+                image_shape: [3, 48, 320],
+                infer_image_shape: false,
+                scripts: vec![RecScript::Hangul],
+                score_thresh,
+            }],
+            ..Default::default()
+        })?;
+        let res = session.run(image)?;
+        let texts = res.rec_result.0.iter().map(|r| r.text.as_str());
+        assert!(texts.eq(["hello", expected_text]));
+        assert!((res.rec_result.0[1].score - expected_score).abs() < 1e-6);
+        Ok(())
+    }
+}
@@ -1,13 +1,23 @@
+#[cfg(feature = "backend-candle")]
+pub mod candle_worker;
+#[cfg(any(test, feature = "backend-mock"))]
+pub mod mock_worker;
 #[cfg(feature = "backend-ort")]
 pub mod ort_worker;
+#[cfg(feature = "backend-tract")]
+pub mod tract_worker;
 
 use crate::error::{RettoError, RettoResult};
+use crate::language::{Language, PpOcrV5Model};
 use crate::serde::*;
 use ndarray::prelude::*;
+use sha2::Digest;
 use std::fmt::Debug;
+use std::io::Read;
 
+/// A [RettoWorkerModelSource] ready to be loaded by a backend
 #[derive(Debug, Clone)]
-pub(crate) enum RettoWorkerModelResolvedSource {
+pub enum RettoWorkerModelResolvedSource {
     #[cfg(not(target_family = "wasm"))]
     Path(std::path::PathBuf),
     Blob(Vec<u8>),
@@ -24,10 +34,47 @@ pub enum RettoWorkerModelSource {
         repo: String,
         model: String,
     },
+    /// `source` checked against its expected SHA-256, see [RettoWorkerModelSource::with_sha256]
+    Verified {
+        source: Box<RettoWorkerModelSource>,
+        sha256: String,
+    },
 }
 
 impl RettoWorkerModelSource {
-    pub(crate) fn resolve(self) -> RettoResult<RettoWorkerModelResolvedSource> {
+    /// Expect the content of this source to have the SHA-256 `sha256` (hex), a mismatch
+    /// fails [RettoWorkerModelSource::resolve] with [RettoError::ModelChecksumError]
+    pub fn with_sha256(self, sha256: impl Into<String>) -> Self {
+        RettoWorkerModelSource::Verified {
+            source: Box::new(self),
+            sha256: sha256.into(),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            #[cfg(not(target_family = "wasm"))]
+            RettoWorkerModelSource::Path(path) => path.clone(),
+            RettoWorkerModelSource::Blob(blob) => format!("blob of {} bytes", blob.len()),
+            #[cfg(feature = "hf-hub")]
+            RettoWorkerModelSource::HuggingFace { repo, model } => format!("{repo}/{model}"),
+            RettoWorkerModelSource::Verified { source, .. } => source.describe(),
+        }
+    }
+
+    /// [RettoWorkerModelSource::resolve], naming the `role` of the model (det, cls, rec)
+    /// in the checksum errors
+    pub fn resolve_as(self, role: &str) -> RettoResult<RettoWorkerModelResolvedSource> {
+        self.resolve().map_err(|e| match e {
+            RettoError::ModelChecksumError(msg) => {
+                RettoError::ModelChecksumError(format!("{role} model, {msg}"))
+            }
+            e => e,
+        })
+    }
+
+    /// Check that the model exists, downloading it first from HF Hub if needed
+    pub fn resolve(self) -> RettoResult<RettoWorkerModelResolvedSource> {
         match self {
             #[cfg(not(target_family = "wasm"))]
             RettoWorkerModelSource::Path(path) => {
@@ -52,10 +99,40 @@ impl RettoWorkerModelSource {
                 let path = helper.get_model_file(&repo, &model)?;
                 Ok(RettoWorkerModelResolvedSource::Path(path))
             }
+            RettoWorkerModelSource::Verified { source, sha256 } => {
+                let name = source.describe();
+                let resolved = source.resolve()?;
+                let actual = match &resolved {
+                    #[cfg(not(target_family = "wasm"))]
+                    RettoWorkerModelResolvedSource::Path(path) => {
+                        sha256_hex(std::fs::File::open(path)?)?
+                    }
+                    RettoWorkerModelResolvedSource::Blob(blob) => sha256_hex(blob.as_slice())?,
+                };
+                match actual.eq_ignore_ascii_case(sha256.trim()) {
+                    true => Ok(resolved),
+                    false => Err(RettoError::ModelChecksumError(format!(
+                        "{name}: expected SHA-256 {sha256}, got {actual}"
+                    ))),
+                }
+            }
         }
     }
 }
 
+/// Hex SHA-256 of everything `reader` yields
+pub(crate) fn sha256_hex(mut reader: impl Read) -> RettoResult<String> {
+    let mut hasher = sha2::Sha256::new();
+    std::io::copy(&mut reader, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect())
+}
+
+/// The PaddleOCR ONNX exports, see [RettoWorkerModelProviderBuilder] for the presets.
+/// Only the PP-OCRv4 Chinese models are published on HF Hub.
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct RettoWorkerModelProvider {
@@ -64,20 +141,138 @@ pub struct RettoWorkerModelProvider {
     pub cls: RettoWorkerModelSource,
 }
 
-// TODO: Split each worker into different cases so that GAT can be fully utilised,
-// TODO: and take advantage of the metadata functionality of the ONNX model
-pub(crate) trait RettoInnerWorker {
+/// Name and shape of the first input and output of a model, `None` dimensions are dynamic
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RettoWorkerModelIO {
+    pub input_name: String,
+    pub input_shape: Vec<Option<usize>>,
+    pub output_name: String,
+    pub output_shape: Vec<Option<usize>>,
+}
+
+impl RettoWorkerModelIO {
+    /// Fixed size of input dimension `axis`, `None` if it is dynamic
+    pub fn input_dim(&self, axis: usize) -> Option<usize> {
+        self.input_shape.get(axis).copied().flatten()
+    }
+
+    /// Fixed size of output dimension `axis`, `None` if it is dynamic
+    pub fn output_dim(&self, axis: usize) -> Option<usize> {
+        self.output_shape.get(axis).copied().flatten()
+    }
+
+    pub(crate) fn check_rank(
+        &self,
+        stage: &str,
+        input_rank: usize,
+        output_rank: usize,
+    ) -> RettoResult<()> {
+        if self.input_shape.len() != input_rank || self.output_shape.len() != output_rank {
+            return Err(RettoError::ModelConfigMismatchError(format!(
+                "{stage} model takes {:?} and returns {:?}, expected {input_rank}-d input and {output_rank}-d output",
+                self.input_shape, self.output_shape
+            )));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn check_output_dim(
+        &self,
+        stage: &str,
+        axis: usize,
+        expected: usize,
+        what: &str,
+    ) -> RettoResult<()> {
+        match self.output_dim(axis) {
+            Some(dim) if dim != expected => Err(RettoError::ModelConfigMismatchError(format!(
+                "{stage} model outputs {dim} {what}, but the config has {expected}"
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// `[C, H, W]` of an NCHW image input: the configured shape is checked against the fixed
+    /// dimensions of the model, unless `infer` is set and those replace it
+    pub(crate) fn resolve_image_shape(
+        &self,
+        stage: &str,
+        configured: [usize; 3],
+        infer: bool,
+    ) -> RettoResult<[usize; 3]> {
+        let model_dims = [self.input_dim(1), self.input_dim(2), self.input_dim(3)];
+        match infer {
+            true => Ok(std::array::from_fn(|i| {
+                model_dims[i].unwrap_or(configured[i])
+            })),
+            false
+                if configured
+                    .iter()
+                    .zip(model_dims)
+                    .any(|(&c, m)| m.is_some_and(|m| m != c)) =>
+            {
+                Err(RettoError::ModelConfigMismatchError(format!(
+                    "{stage} image_shape is {configured:?}, but the model input is {:?}",
+                    self.input_shape
+                )))
+            }
+            false => Ok(configured),
+        }
+    }
+}
+
+// TODO: Split each worker into different cases so that GAT can be fully utilised
+/// Inference surface of a backend, implement it together with [RettoWorker] to plug another
+/// engine (remote inference, custom runtime, ...) into [crate::session::RettoSession].
+///
+/// The session does the pre- and post-processing, a backend only runs the models on
+/// normalized `f32` tensors in NCHW layout.
+pub trait RettoInnerWorker {
+    /// Run the det model on `[N, 3, H, W]`, returns the `[N, 1, H, W]` text probability map
     fn det(&mut self, input: Array4<f32>) -> RettoResult<Array4<f32>>;
+    /// Run the cls model on `[N, 3, H, W]` line crops, returns the `[N, labels]`
+    /// probabilities of [crate::processor::cls_processor::ClsProcessorConfig::label]
     fn cls(&mut self, input: Array4<f32>) -> RettoResult<Array2<f32>>;
-    fn rec(&mut self, input: Array4<f32>) -> RettoResult<Array3<f32>>;
+    /// Run the rec model `model` on `[N, 3, H, W]` line crops, returns the `[N, T, classes]`
+    /// probabilities of every timestep. Model 0 is the one of the provider, the others were
+    /// added with [RettoInnerWorker::add_rec_model].
+    fn rec(&mut self, model: usize, input: Array4<f32>) -> RettoResult<Array3<f32>>;
+    /// Custom metadata `key` of the rec model `model`, `None` if the model does not have it
+    fn rec_metadata(&self, model: usize, key: &str) -> RettoResult<Option<String>>;
+    /// Load another rec model, returns its index
+    fn add_rec_model(&mut self, source: RettoWorkerModelSource) -> RettoResult<usize>;
+    /// Signature of the det model, checked by the session before the first run
+    fn det_io(&self) -> &RettoWorkerModelIO;
+    /// Signature of the cls model, its fixed dimensions set the crop size
+    fn cls_io(&self) -> &RettoWorkerModelIO;
+    /// Signature of the rec model `model`, its fixed dimensions set the crop size
+    fn rec_io(&self, model: usize) -> &RettoWorkerModelIO;
 }
 
 pub trait RettoWorkerModelProviderBuilder: Debug + Clone + MaybeSerde {
+    /// PP-OCRv4 det / cls models and the rec model of `language` from HF Hub, fails with
+    /// [RettoError::ModelNotFoundError] if the rec model is not published there
+    /// (see [Language::is_on_hf_hub]), use [Self::from_local_v4_language_path] instead
+    #[cfg(all(not(target_family = "wasm"), feature = "hf-hub"))]
+    fn from_hf_hub_v4_language(language: Language) -> RettoResult<Self>;
+    /// PP-OCRv4 det / cls models and the rec model of `language` from the working directory
+    #[cfg(not(target_family = "wasm"))]
+    fn from_local_v4_language_path(language: Language) -> Self;
     #[cfg(all(not(target_family = "wasm"), feature = "hf-hub"))]
-    fn from_hf_hub_v4_default() -> Self;
+    fn from_hf_hub_v4_default() -> Self {
+        Self::from_hf_hub_v4_language(Language::default())
+            .expect("the models of the default language are published on HF Hub")
+    }
     #[cfg(not(target_family = "wasm"))]
-    fn from_local_v4_path_default() -> Self;
+    fn from_local_v4_path_default() -> Self {
+        Self::from_local_v4_language_path(Language::default())
+    }
     fn from_local_v4_blob_default() -> Self;
+    /// PP-OCRv5 det / rec models of the given size and the shared cls model
+    /// from the working directory. The PP-OCRv5 files are not published on HF Hub,
+    /// so there is no HF Hub counterpart.
+    #[cfg(not(target_family = "wasm"))]
+    fn from_local_v5_path(model: PpOcrV5Model) -> Self;
     fn default_provider() -> Self {
         #[cfg(all(not(target_family = "wasm"), feature = "hf-hub"))]
         return Self::from_hf_hub_v4_default();
@@ -88,19 +283,296 @@ pub trait RettoWorkerModelProviderBuilder: Debug + Clone + MaybeSerde {
     }
 }
 
+impl RettoWorkerModelProviderBuilder for RettoWorkerModelProvider {
+    #[cfg(all(not(target_family = "wasm"), feature = "hf-hub"))]
+    fn from_hf_hub_v4_language(language: Language) -> RettoResult<Self> {
+        if !language.is_on_hf_hub() {
+            return Err(RettoError::ModelNotFoundError(format!(
+                "{} is not published on HF Hub",
+                language.v4_rec_model_file()
+            )));
+        }
+        Ok(RettoWorkerModelProvider {
+            det: Language::hf_hub_source("ch_PP-OCRv4_det_infer.onnx"),
+            rec: Language::hf_hub_source(language.v4_rec_model_file()),
+            cls: Language::hf_hub_source("ch_ppocr_mobile_v2.0_cls_infer.onnx"),
+        })
+    }
+
+    #[cfg(not(target_family = "wasm"))]
+    fn from_local_v4_language_path(language: Language) -> Self {
+        RettoWorkerModelProvider {
+            det: RettoWorkerModelSource::Path("ch_PP-OCRv4_det_infer.onnx".into()),
+            rec: RettoWorkerModelSource::Path(language.v4_rec_model_file().into()),
+            cls: RettoWorkerModelSource::Path("ch_ppocr_mobile_v2.0_cls_infer.onnx".into()),
+        }
+    }
+
+    #[cfg(feature = "download-models")]
+    fn from_local_v4_blob_default() -> Self {
+        RettoWorkerModelProvider {
+            det: RettoWorkerModelSource::Blob(
+                include_bytes!("../models/ch_PP-OCRv4_det_infer.onnx").to_vec(),
+            ),
+            rec: RettoWorkerModelSource::Blob(
+                include_bytes!("../models/ch_PP-OCRv4_rec_infer.onnx").to_vec(),
+            ),
+            cls: RettoWorkerModelSource::Blob(
+                include_bytes!("../models/ch_ppocr_mobile_v2.0_cls_infer.onnx").to_vec(),
+            ),
+        }
+    }
+
+    #[cfg(not(feature = "download-models"))]
+    fn from_local_v4_blob_default() -> Self {
+        RettoWorkerModelProvider {
+            det: RettoWorkerModelSource::Blob(Vec::new()),
+            rec: RettoWorkerModelSource::Blob(Vec::new()),
+            cls: RettoWorkerModelSource::Blob(Vec::new()),
+        }
+    }
+
+    #[cfg(not(target_family = "wasm"))]
+    fn from_local_v5_path(model: PpOcrV5Model) -> Self {
+        RettoWorkerModelProvider {
+            det: RettoWorkerModelSource::Path(model.det_model_file().into()),
+            rec: RettoWorkerModelSource::Path(model.rec_model_file().into()),
+            cls: RettoWorkerModelSource::Path("ch_ppocr_mobile_v2.0_cls_infer.onnx".into()),
+        }
+    }
+}
+
+impl Default for RettoWorkerModelProvider {
+    fn default() -> Self {
+        Self::default_provider()
+    }
+}
+
+/// A backend [crate::session::RettoSession] can run on
 pub trait RettoWorker: RettoInnerWorker {
+    /// Where the models come from, [RettoWorkerModelProvider] fits backends reading the
+    /// PaddleOCR ONNX exports
     type RettoWorkerModelProvider: RettoWorkerModelProviderBuilder;
     type RettoWorkerConfig: Debug + Default + Clone + MaybeSerde;
+    /// Load the models of `cfg`
     fn new(cfg: Self::RettoWorkerConfig) -> RettoResult<Self>
     where
         Self: Sized;
+    /// Called once by the session after the model signatures were checked
     fn init(&self) -> RettoResult<()>;
 }
 
 pub mod prelude {
+    #[cfg(feature = "backend-candle")]
+    pub use super::candle_worker::*;
+    #[cfg(any(test, feature = "backend-mock"))]
+    pub use super::mock_worker::*;
     #[cfg(feature = "backend-ort")]
     pub use super::ort_worker::*;
+    #[cfg(feature = "backend-tract")]
+    pub use super::tract_worker::*;
     pub use super::{
-        RettoWorkerModelProvider, RettoWorkerModelProviderBuilder, RettoWorkerModelSource,
+        RettoInnerWorker, RettoWorker, RettoWorkerModelIO, RettoWorkerModelProvider,
+        RettoWorkerModelProviderBuilder, RettoWorkerModelResolvedSource, RettoWorkerModelSource,
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::*;
+
+    #[rstest]
+    #[case(
+        "BA7816BF8F01CFEA414140DE5DAE2223B00361A396177A9CB410FF61F20015AD",
+        true
+    )]
+    #[case(
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ae",
+        false
+    )]
+    fn test_verified_source(#[case] sha256: &str, #[case] matches: bool) {
+        let source = RettoWorkerModelSource::Blob(b"abc".to_vec()).with_sha256(sha256);
+        match source.resolve_as("det") {
+            Ok(RettoWorkerModelResolvedSource::Blob(blob)) => {
+                assert!(matches && blob == b"abc")
+            }
+            Err(RettoError::ModelChecksumError(msg)) => {
+                assert!(!matches && msg.starts_with("det model, blob of 3 bytes"))
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    #[cfg(all(not(target_family = "wasm"), feature = "hf-hub"))]
+    #[rstest]
+    #[case::published(Language::Chinese, true)]
+    #[case::unpublished(Language::Korean, false)]
+    fn test_hf_hub_v4_language(#[case] language: Language, #[case] published: bool) {
+        match RettoWorkerModelProvider::from_hf_hub_v4_language(language) {
+            Ok(provider) => assert!(
+                published && matches!(provider.rec, RettoWorkerModelSource::HuggingFace { .. })
+            ),
+            Err(RettoError::ModelNotFoundError(msg)) => {
+                assert!(!published && msg.starts_with(language.v4_rec_model_file()))
+            }
+            Err(other) => panic!("unexpected {other:?}"),
+        }
+    }
+
+    /// Hand-encoded ONNX model copying its `[N, 3, H, W]` float input `x` to its output `y`,
+    /// with `metadata` as custom metadata, for the smoke tests of the backends
+    #[cfg(any(
+        feature = "backend-ort",
+        feature = "backend-tract",
+        feature = "backend-candle"
+    ))]
+    pub(crate) fn identity_onnx(metadata: &[(&str, &str)]) -> Vec<u8> {
+        typed_identity_onnx(1, metadata)
+    }
+
+    /// Provider with [identity_onnx] as every model
+    #[cfg(any(feature = "backend-tract", feature = "backend-candle"))]
+    pub(crate) fn identity_provider() -> RettoWorkerModelProvider {
+        let source = || RettoWorkerModelSource::Blob(identity_onnx(&[("character", "a\nb")]));
+        RettoWorkerModelProvider {
+            det: source(),
+            rec: source(),
+            cls: source(),
+        }
+    }
+
+    /// Smoke test of a backend loaded with [identity_provider]: signature, metadata and a run
+    #[cfg(any(feature = "backend-tract", feature = "backend-candle"))]
+    pub(crate) fn check_identity_worker<W: RettoInnerWorker>(mut worker: W) -> RettoResult<()> {
+        let io = worker.det_io();
+        assert_eq!(
+            (io.input_name.as_str(), io.output_name.as_str()),
+            ("x", "y")
+        );
+        assert_eq!(io.input_shape, [None, Some(3), None, None]);
+        assert_eq!(
+            worker.rec_metadata(0, "character")?.as_deref(),
+            Some("a\nb")
+        );
+        let input = Array4::from_shape_fn((1, 3, 4, 5), |(_, c, h, w)| (c * 20 + h * 5 + w) as f32);
+        assert_eq!(worker.det(input.clone())?, input);
+        Ok(())
+    }
+
+    /// [identity_onnx] with tensors of the ONNX element type `elem_type`
+    #[cfg(any(
+        feature = "backend-ort",
+        feature = "backend-tract",
+        feature = "backend-candle"
+    ))]
+    pub(crate) fn typed_identity_onnx(elem_type: u64, metadata: &[(&str, &str)]) -> Vec<u8> {
+        fn varint(mut v: u64, buf: &mut Vec<u8>) {
+            while v >= 0x80 {
+                buf.push(v as u8 | 0x80);
+                v >>= 7;
+            }
+            buf.push(v as u8);
+        }
+        fn int(field: u64, v: u64) -> Vec<u8> {
+            let mut buf = Vec::new();
+            varint(field << 3, &mut buf);
+            varint(v, &mut buf);
+            buf
+        }
+        fn bytes(field: u64, data: &[u8]) -> Vec<u8> {
+            let mut buf = Vec::new();
+            varint(field << 3 | 2, &mut buf);
+            varint(data.len() as u64, &mut buf);
+            buf.extend_from_slice(data);
+            buf
+        }
+        // TensorShapeProto.Dimension: dim_value = 1, dim_param = 2
+        let dims = [bytes(2, b"N"), int(1, 3), bytes(2, b"H"), bytes(2, b"W")]
+            .iter()
+            .flat_map(|dim| bytes(1, dim))
+            .collect::<Vec<_>>();
+        // ValueInfoProto { name, type: TypeProto { tensor_type: { elem_type, shape } } }
+        let value_info = |name: &str| {
+            let tensor = [int(1, elem_type), bytes(2, &dims)].concat();
+            [bytes(1, name.as_bytes()), bytes(2, &bytes(1, &tensor))].concat()
+        };
+        // NodeProto { input, output, name, op_type }
+        let node = [
+            bytes(1, b"x"),
+            bytes(2, b"y"),
+            bytes(3, b"identity"),
+            bytes(4, b"Identity"),
+        ]
+        .concat();
+        // GraphProto { node, name, input, output }
+        let graph = [
+            bytes(1, &node),
+            bytes(2, b"graph"),
+            bytes(11, &value_info("x")),
+            bytes(12, &value_info("y")),
+        ]
+        .concat();
+        // ModelProto { ir_version, graph, opset_import: { domain, version }, metadata_props }
+        let mut model = [
+            int(1, 8),
+            bytes(7, &graph),
+            bytes(8, &[bytes(1, b""), int(2, 13)].concat()),
+        ]
+        .concat();
+        for (key, value) in metadata {
+            model.extend(bytes(
+                14,
+                &[bytes(1, key.as_bytes()), bytes(2, value.as_bytes())].concat(),
+            ));
+        }
+        model
+    }
+
+    fn rec_io() -> RettoWorkerModelIO {
+        RettoWorkerModelIO {
+            input_name: "x".to_string(),
+            input_shape: vec![None, Some(3), Some(48), None],
+            output_name: "y".to_string(),
+            output_shape: vec![None, None, Some(6625)],
+        }
+    }
+
+    #[rstest]
+    #[case(4, 3, true)]
+    #[case(4, 2, false)]
+    #[case(3, 3, false)]
+    fn test_check_rank(#[case] input_rank: usize, #[case] output_rank: usize, #[case] ok: bool) {
+        assert_eq!(
+            rec_io().check_rank("rec", input_rank, output_rank).is_ok(),
+            ok
+        );
+    }
+
+    #[rstest]
+    #[case(2, 6625, true)]
+    #[case(2, 6624, false)]
+    // dynamic dimensions match anything
+    #[case(1, 10, true)]
+    fn test_check_output_dim(#[case] axis: usize, #[case] expected: usize, #[case] ok: bool) {
+        let res = rec_io().check_output_dim("rec", axis, expected, "classes");
+        assert_eq!(res.is_ok(), ok);
+    }
+
+    #[rstest]
+    #[case([3, 48, 320], false, Some([3, 48, 320]))]
+    #[case([3, 32, 320], false, None)]
+    #[case([3, 32, 320], true, Some([3, 48, 320]))]
+    #[case([1, 32, 100], true, Some([3, 48, 100]))]
+    fn test_resolve_image_shape(
+        #[case] configured: [usize; 3],
+        #[case] infer: bool,
+        #[case] expected: Option<[usize; 3]>,
+    ) {
+        match rec_io().resolve_image_shape("rec", configured, infer) {
+            Ok(shape) => assert_eq!(Some(shape), expected),
+            Err(RettoError::ModelConfigMismatchError(_)) => assert_eq!(expected, None),
+            Err(e) => panic!("unexpected {e:?}"),
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use crate::error::{RettoError, RettoResult};
+use crate::serde::*;
+use crate::worker::{
+    RettoInnerWorker, RettoWorker, RettoWorkerModelIO, RettoWorkerModelProvider,
+    RettoWorkerModelResolvedSource, RettoWorkerModelSource,
+};
+use candle_core::{Device, Tensor};
+use candle_onnx::onnx::{ModelProto, ValueInfoProto, tensor_shape_proto, type_proto};
+use ndarray::prelude::*;
+use prost::Message;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RettoCandleWorkerConfig {
+    pub models: RettoWorkerModelProvider,
+}
+
+/// ONNX graph evaluated by candle, with its signature and custom metadata.
+/// candle-onnx has no compiled form of a graph, every run walks the proto again and
+/// converts the weights to tensors.
+#[derive(Debug)]
+struct CandleModel {
+    proto: ModelProto,
+    io: RettoWorkerModelIO,
+    metadata: HashMap<String, String>,
+}
+
+impl CandleModel {
+    fn load(source: RettoWorkerModelSource, role: &str) -> RettoResult<Self> {
+        let proto = match source.resolve_as(role)? {
+            #[cfg(not(target_family = "wasm"))]
+            RettoWorkerModelResolvedSource::Path(path) => candle_onnx::read_file(path)?,
+            RettoWorkerModelResolvedSource::Blob(blob) => {
+                ModelProto::decode(blob.as_slice()).map_err(candle_core::Error::wrap)?
+            }
+        };
+        let metadata = proto
+            .metadata_props
+            .iter()
+            .map(|prop| (prop.key.clone(), prop.value.clone()))
+            .collect();
+        let io = Self::model_io(&proto)?;
+        Ok(CandleModel {
+            proto,
+            io,
+            metadata,
+        })
+    }
+
+    fn model_io(proto: &ModelProto) -> RettoResult<RettoWorkerModelIO> {
+        let shape =
+            |value: &ValueInfoProto| match value.r#type.as_ref().and_then(|t| t.value.as_ref()) {
+                Some(type_proto::Value::TensorType(tensor)) => tensor
+                    .shape
+                    .iter()
+                    .flat_map(|shape| &shape.dim)
+                    .map(|dim| match dim.value {
+                        Some(tensor_shape_proto::dimension::Value::DimValue(d)) => {
+                            usize::try_from(d).ok().filter(|&d| d > 0)
+                        }
+                        _ => None,
+                    })
+                    .collect(),
+                _ => Vec::new(),
+            };
+        let graph = proto.graph.as_ref().ok_or_else(|| {
+            RettoError::ModelConfigMismatchError("model has no graph".to_string())
+        })?;
+        // Older exports also list the weights as graph inputs
+        let initializers = graph
+            .initializer
+            .iter()
+            .map(|t| t.name.as_str())
+            .collect::<HashSet<_>>();
+        let (input, output) = graph
+            .input
+            .iter()
+            .find(|input| !initializers.contains(input.name.as_str()))
+            .zip(graph.output.first())
+            .ok_or_else(|| {
+                RettoError::ModelConfigMismatchError("model has no input or output".to_string())
+            })?;
+        Ok(RettoWorkerModelIO {
+            input_name: input.name.clone(),
+            input_shape: shape(input),
+            output_name: output.name.clone(),
+            output_shape: shape(output),
+        })
+    }
+
+    fn run<D: Dimension>(&self, input: Array4<f32>) -> RettoResult<Array<f32, D>> {
+        let data = input.iter().copied().collect::<Vec<_>>();
+        let tensor = Tensor::from_vec(data, input.shape(), &Device::Cpu)?;
+        let mut outputs = candle_onnx::simple_eval(
+            &self.proto,
+            HashMap::from([(self.io.input_name.clone(), tensor)]),
+        )?;
+        let output = outputs.remove(&self.io.output_name).ok_or_else(|| {
+            RettoError::ModelConfigMismatchError(format!("no output `{}`", self.io.output_name))
+        })?;
+        let shape = output.dims().to_vec();
+        let data = output.flatten_all()?.to_vec1::<f32>()?;
+        Ok(ArrayD::from_shape_vec(shape, data)?.into_dimensionality::<D>()?)
+    }
+}
+
+/// Pure-Rust CPU backend evaluating the ONNX graphs with candle. The quantized kernels of
+/// candle only load GGUF weights, so there is no quantized path, use
+/// the ort backend with a pre-quantized model instead. Only ONNX models are loaded,
+/// safetensors conversions of the PaddleOCR models are out of scope.
+///
+/// Building `backend-candle` requires `protoc`, candle-onnx generates its ONNX bindings
+/// at build time.
+#[derive(Debug)]
+pub struct RettoCandleWorker {
+    det: CandleModel,
+    cls: CandleModel,
+    /// The rec model of the provider, then the ones added with
+    /// [RettoInnerWorker::add_rec_model]
+    recs: Vec<CandleModel>,
+}
+
+impl RettoWorker for RettoCandleWorker {
+    type RettoWorkerModelProvider = RettoWorkerModelProvider;
+    type RettoWorkerConfig = RettoCandleWorkerConfig;
+
+    fn new(cfg: Self::RettoWorkerConfig) -> RettoResult<Self>
+    where
+        Self: Sized,
+    {
+        let det = CandleModel::load(cfg.models.det.clone(), "det")?;
+        let cls = CandleModel::load(cfg.models.cls.clone(), "cls")?;
+        let rec = CandleModel::load(cfg.models.rec.clone(), "rec")?;
+        Ok(RettoCandleWorker {
+            det,
+            cls,
+            recs: vec![rec],
+        })
+    }
+
+    fn init(&self) -> RettoResult<()> {
+        Ok(())
+    }
+}
+
+impl RettoInnerWorker for RettoCandleWorker {
+    fn det(&mut self, input: Array4<f32>) -> RettoResult<Array4<f32>> {
+        self.det.run(input)
+    }
+
+    fn cls(&mut self, input: Array4<f32>) -> RettoResult<Array2<f32>> {
+        self.cls.run(input)
+    }
+
+    fn rec(&mut self, model: usize, input: Array4<f32>) -> RettoResult<Array3<f32>> {
+        self.recs[model].run(input)
+    }
+
+    fn rec_metadata(&self, model: usize, key: &str) -> RettoResult<Option<String>> {
+        Ok(self.recs[model].metadata.get(key).cloned())
+    }
+
+    fn add_rec_model(&mut self, source: RettoWorkerModelSource) -> RettoResult<usize> {
+        self.recs.push(CandleModel::load(source, "rec")?);
+        Ok(self.recs.len() - 1)
+    }
+
+    fn det_io(&self) -> &RettoWorkerModelIO {
+        &self.det.io
+    }
+
+    fn cls_io(&self) -> &RettoWorkerModelIO {
+        &self.cls.io
+    }
+
+    fn rec_io(&self, model: usize) -> &RettoWorkerModelIO {
+        &self.recs[model].io
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::worker::tests::{check_identity_worker, identity_provider};
+
+    #[test]
+    fn test_identity_model() -> RettoResult<()> {
+        check_identity_worker(RettoCandleWorker::new(RettoCandleWorkerConfig {
+            models: identity_provider(),
+        })?)
+    }
+}
@@ -0,0 +1,367 @@
+use crate::error::{RettoError, RettoResult};
+#[cfg(test)]
+use crate::image_helper::ImageHelper;
+use crate::processor::prelude::*;
+use crate::serde::*;
+use crate::worker::{
+    RettoInnerWorker, RettoWorker, RettoWorkerModelIO, RettoWorkerModelProvider,
+    RettoWorkerModelSource,
+};
+use image::{ImageFormat, Rgb, RgbImage};
+use ndarray::prelude::*;
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap};
+use std::io::Cursor;
+
+/// A scripted text line of [RettoMockWorkerConfig]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RettoMockLine {
+    /// `[x0, y0, x1, y1]` as fractions of the image size
+    pub rect: [f32; 4],
+    pub text: String,
+    /// Whether the cls model reports the line as upside down
+    pub flipped: bool,
+}
+
+/// Lines the mock models "see". Feed the session with [RettoMockWorkerConfig::render],
+/// every line is painted in its own gray level so the crops can be told apart.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RettoMockWorkerConfig {
+    pub lines: Vec<RettoMockLine>,
+    /// Probability of the emitted characters in the output of each successive rec call,
+    /// [RettoMockWorkerConfig::CHAR_PROB] once exhausted. Makes the first pass score low.
+    pub rec_call_probs: Vec<f32>,
+    /// Characters each rec model knows, by model index. A model without an entry knows
+    /// every character of the lines, the others read an unknown character as the first one
+    /// they know with [RettoMockWorkerConfig::UNKNOWN_PROB].
+    pub rec_charsets: Vec<String>,
+}
+
+impl RettoMockWorkerConfig {
+    /// Probability of the emitted characters in the rec output, the rest goes to the blank
+    pub const CHAR_PROB: f32 = 0.98;
+    /// Probability of a character a rec model does not know in its output
+    pub const UNKNOWN_PROB: f32 = 0.6;
+    const MAX_LINES: usize = 28;
+
+    pub fn with_line(mut self, rect: [f32; 4], text: impl Into<String>, flipped: bool) -> Self {
+        self.lines.push(RettoMockLine {
+            rect,
+            text: text.into(),
+            flipped,
+        });
+        self
+    }
+
+    /// Rec config reading the dictionary of the mock rec model
+    pub fn rec_processor_config() -> RecProcessorConfig {
+        RecProcessorConfig {
+            character_source: RecCharacterDictProvider::Inline(),
+            ..Default::default()
+        }
+    }
+
+    /// PNG image of the lines on a black background
+    pub fn render(&self, width: u32, height: u32) -> RettoResult<Vec<u8>> {
+        let mut image = RgbImage::new(width, height);
+        for (i, line) in self.lines.iter().enumerate() {
+            let [x0, y0, x1, y1] = line.rect;
+            let (x0, x1) = ((x0 * width as f32) as u32, (x1 * width as f32) as u32);
+            let (y0, y1) = ((y0 * height as f32) as u32, (y1 * height as f32) as u32);
+            for y in y0..y1.min(height) {
+                for x in x0..x1.min(width) {
+                    image.put_pixel(x, y, Rgb([Self::line_level(i); 3]));
+                }
+            }
+        }
+        let mut buf = Vec::new();
+        image.write_to(&mut Cursor::new(&mut buf), ImageFormat::Png)?;
+        Ok(buf)
+    }
+
+    fn line_level(line: usize) -> u8 {
+        (32 + 8 * line) as u8
+    }
+
+    /// Crop of `line` as cut by the det stage, with a white top-left pixel to tell its
+    /// orientation
+    #[cfg(test)]
+    pub(crate) fn line_crop(&self, line: usize, width: u32, height: u32) -> ImageHelper {
+        let mut image = RgbImage::from_pixel(width, height, Rgb([Self::line_level(line); 3]));
+        image.put_pixel(0, 0, Rgb([255; 3]));
+        ImageHelper::new_from_rgb_image(image)
+    }
+
+    /// Line painted in the normalized crop `crop`, `None` for the background or a crop
+    /// altered by a retry strategy
+    fn line_of(&self, crop: ArrayView3<f32>) -> Option<&RettoMockLine> {
+        let (_, h, _) = crop.dim();
+        // The most common level of the middle row, the crop may include some background and
+        // the right padding is exactly 0, which no pixel level maps to
+        let mut counts = HashMap::new();
+        crop.slice(s![0, h / 2, ..])
+            .iter()
+            .filter(|&&v| v != 0.0)
+            .map(|&v| ((v * 0.5 + 0.5) * 255.0 - 32.0) / 8.0)
+            .filter(|line| *line > -0.25 && (line - line.round()).abs() < 0.25)
+            .for_each(|line| *counts.entry(line.round() as usize).or_insert(0) += 1);
+        counts
+            .into_iter()
+            .max_by_key(|&(line, count)| (count, Reverse(line)))
+            .and_then(|(line, _)| self.lines.get(line))
+    }
+}
+
+/// Backend returning scripted outputs instead of running models, for tests without
+/// network or model files. The det map is synthesized from the boxes of the lines, the cls
+/// logits from their orientation and the rec logits from their text.
+#[derive(Debug)]
+pub struct RettoMockWorker {
+    cfg: RettoMockWorkerConfig,
+    /// Dictionary of each rec model, see [RettoMockWorkerConfig::rec_charsets]
+    dicts: Vec<Vec<char>>,
+    rec_calls: usize,
+    det_io: RettoWorkerModelIO,
+    cls_io: RettoWorkerModelIO,
+    rec_ios: Vec<RettoWorkerModelIO>,
+}
+
+impl RettoMockWorker {
+    /// Distance the box of a line is shrunk by in the det map, so that the default
+    /// [DetProcessorConfig::unclip_ratio] expands it back: `d = A' * ratio / L'` where
+    /// `A'` and `L'` are the area and perimeter of the shrunk box
+    fn shrink_offset(w: f32, h: f32) -> f32 {
+        let ratio = DetProcessorConfig::default().unclip_ratio;
+        let a = 8.0 + 4.0 * ratio;
+        let b = (2.0 + 2.0 * ratio) * (w + h);
+        let c = ratio * w * h;
+        (b - (b * b - 4.0 * a * c).max(0.0).sqrt()) / (2.0 * a)
+    }
+
+    fn io(input_shape: Vec<Option<usize>>, output_shape: Vec<Option<usize>>) -> RettoWorkerModelIO {
+        RettoWorkerModelIO {
+            input_name: "x".to_string(),
+            input_shape,
+            output_name: "y".to_string(),
+            output_shape,
+        }
+    }
+
+    /// Sorted characters known by rec model `model`, without the space
+    fn dict_of(cfg: &RettoMockWorkerConfig, model: usize) -> Vec<char> {
+        match cfg.rec_charsets.get(model) {
+            Some(charset) => charset.chars().collect::<BTreeSet<_>>(),
+            None => cfg
+                .lines
+                .iter()
+                .flat_map(|line| line.text.chars())
+                .collect(),
+        }
+        .into_iter()
+        .filter(|&c| c != ' ')
+        .collect()
+    }
+
+    fn push_rec_model(&mut self) -> usize {
+        let dict = Self::dict_of(&self.cfg, self.dicts.len());
+        self.rec_ios.push(Self::io(
+            vec![None, Some(3), Some(48), None],
+            vec![None, None, Some(dict.len() + 2)],
+        ));
+        self.dicts.push(dict);
+        self.dicts.len() - 1
+    }
+
+    /// Rec output index of `c` for `dict` and whether it is known, the blank is 0 and the
+    /// space is appended to the dictionary
+    fn class_of(dict: &[char], c: char) -> (usize, bool) {
+        match c {
+            ' ' => (dict.len() + 1, true),
+            _ => dict
+                .binary_search(&c)
+                .map_or((1.min(dict.len()), false), |i| (i + 1, true)),
+        }
+    }
+}
+
+impl RettoWorker for RettoMockWorker {
+    type RettoWorkerModelProvider = RettoWorkerModelProvider;
+    type RettoWorkerConfig = RettoMockWorkerConfig;
+
+    fn new(cfg: Self::RettoWorkerConfig) -> RettoResult<Self>
+    where
+        Self: Sized,
+    {
+        if cfg.lines.len() > RettoMockWorkerConfig::MAX_LINES {
+            return Err(RettoError::ModelConfigMismatchError(format!(
+                "the mock worker supports up to {} lines",
+                RettoMockWorkerConfig::MAX_LINES
+            )));
+        }
+        let mut worker = RettoMockWorker {
+            det_io: Self::io(
+                vec![None, Some(3), None, None],
+                vec![None, Some(1), None, None],
+            ),
+            cls_io: Self::io(
+                vec![None, Some(3), Some(48), Some(192)],
+                vec![None, Some(2)],
+            ),
+            cfg,
+            dicts: Vec::new(),
+            rec_calls: 0,
+            rec_ios: Vec::new(),
+        };
+        worker.push_rec_model();
+        Ok(worker)
+    }
+
+    fn init(&self) -> RettoResult<()> {
+        Ok(())
+    }
+}
+
+impl RettoInnerWorker for RettoMockWorker {
+    fn det(&mut self, input: Array4<f32>) -> RettoResult<Array4<f32>> {
+        let (n, _, h, w) = input.dim();
+        let mut map = Array4::zeros((n, 1, h, w));
+        for line in &self.cfg.lines {
+            let [x0, y0, x1, y1] = line.rect;
+            let (x0, x1) = (x0 * w as f32, x1 * w as f32);
+            let (y0, y1) = (y0 * h as f32, y1 * h as f32);
+            let offset = Self::shrink_offset(x1 - x0, y1 - y0);
+            let xs = (x0 + offset).round() as usize..(x1 - offset).round() as usize;
+            let ys = (y0 + offset).round() as usize..(y1 - offset).round() as usize;
+            map.slice_mut(s![
+                ..,
+                0,
+                ys.start.min(h)..ys.end.min(h),
+                xs.start.min(w)..xs.end.min(w)
+            ])
+            .fill(1.0);
+        }
+        Ok(map)
+    }
+
+    fn cls(&mut self, input: Array4<f32>) -> RettoResult<Array2<f32>> {
+        let logits = input
+            .outer_iter()
+            .map(
+                |crop| match self.cfg.line_of(crop).is_some_and(|l| l.flipped) {
+                    true => [0.02, 0.98],
+                    false => [0.98, 0.02],
+                },
+            )
+            .collect::<Vec<_>>();
+        Ok(Array2::from(logits))
+    }
+
+    fn rec(&mut self, model: usize, input: Array4<f32>) -> RettoResult<Array3<f32>> {
+        let (n, _, _, w) = input.dim();
+        // PP-OCR rec models downsample the width by 8
+        let steps = w.div_ceil(8);
+        let char_prob = self
+            .cfg
+            .rec_call_probs
+            .get(self.rec_calls)
+            .copied()
+            .unwrap_or(RettoMockWorkerConfig::CHAR_PROB);
+        self.rec_calls += 1;
+        let dict = &self.dicts[model];
+        let mut probs = Array3::zeros((n, steps, dict.len() + 2));
+        probs.slice_mut(s![.., .., 0]).fill(1.0);
+        for (i, crop) in input.outer_iter().enumerate() {
+            let Some(line) = self.cfg.line_of(crop) else {
+                continue;
+            };
+            if line.text.chars().count() * 2 > steps {
+                return Err(RettoError::ModelConfigMismatchError(format!(
+                    "`{}` does not fit in {steps} timesteps",
+                    line.text
+                )));
+            }
+            // A blank between characters, so that repeated characters are kept
+            for (k, c) in line.text.chars().enumerate() {
+                let (class, known) = Self::class_of(dict, c);
+                let prob = match known {
+                    true => char_prob,
+                    false => RettoMockWorkerConfig::UNKNOWN_PROB,
+                };
+                probs[[i, 2 * k, 0]] = 1.0 - prob;
+                probs[[i, 2 * k, class]] = prob;
+            }
+        }
+        Ok(probs)
+    }
+
+    fn rec_metadata(&self, model: usize, key: &str) -> RettoResult<Option<String>> {
+        let dict = self.dicts[model]
+            .iter()
+            .map(char::to_string)
+            .collect::<Vec<_>>();
+        Ok((key == "character").then(|| dict.join("\n")))
+    }
+
+    /// The source is ignored, the model knows the characters of its entry in
+    /// [RettoMockWorkerConfig::rec_charsets]
+    fn add_rec_model(&mut self, _source: RettoWorkerModelSource) -> RettoResult<usize> {
+        Ok(self.push_rec_model())
+    }
+
+    fn det_io(&self) -> &RettoWorkerModelIO {
+        &self.det_io
+    }
+
+    fn cls_io(&self) -> &RettoWorkerModelIO {
+        &self.cls_io
+    }
+
+    fn rec_io(&self, model: usize) -> &RettoWorkerModelIO {
+        &self.rec_ios[model]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::{RettoSession, RettoSessionConfig};
+    use rstest::*;
+
+    #[fixture]
+    fn lines() -> RettoMockWorkerConfig {
+        RettoMockWorkerConfig::default()
+            .with_line([0.1, 0.1, 0.6, 0.2], "hello world", false)
+            .with_line([0.1, 0.5, 0.9, 0.6], "玩原神", true)
+    }
+
+    #[rstest]
+    fn test_session_with_mock(lines: RettoMockWorkerConfig) -> RettoResult<()> {
+        let (w, h) = (400, 300);
+        let image = lines.render(w, h)?;
+        let mut session = RettoSession::new(RettoSessionConfig::<RettoMockWorker> {
+            worker_config: lines.clone(),
+            rec_processor_config: RettoMockWorkerConfig::rec_processor_config(),
+            ..Default::default()
+        })?;
+        let res = session.run(image)?;
+        assert_eq!(res.det_result.0.len(), lines.lines.len());
+        for (det, line) in res.det_result.0.iter().zip(&lines.lines) {
+            let tl = det.boxes.tl();
+            assert!((tl.x.into_inner() - line.rect[0] * w as f32).abs() < 4.0);
+            assert!((tl.y.into_inner() - line.rect[1] * h as f32).abs() < 4.0);
+        }
+        let labels = res.cls_result.0.iter().map(|r| r.label.label);
+        assert!(labels.eq([0, 180]));
+        let texts = res.rec_result.0.iter().map(|r| r.text.as_str());
+        assert!(texts.eq(["hello world", "玩原神"]));
+        assert!(
+            res.rec_result
+                .0
+                .iter()
+                .all(|r| (r.score - RettoMockWorkerConfig::CHAR_PROB).abs() < 1e-6)
+        );
+        Ok(())
+    }
+}
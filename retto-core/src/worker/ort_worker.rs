@@ -1,21 +1,39 @@
 use crate::error::{RettoError, RettoResult};
+use crate::language::{Language, PpOcrV5Model};
 use crate::serde::*;
 use crate::worker::{
-    RettoInnerWorker, RettoWorker, RettoWorkerModelProvider, RettoWorkerModelProviderBuilder,
-    RettoWorkerModelResolvedSource, RettoWorkerModelSource,
+    RettoInnerWorker, RettoWorker, RettoWorkerModelIO, RettoWorkerModelProvider,
+    RettoWorkerModelProviderBuilder, RettoWorkerModelResolvedSource, RettoWorkerModelSource,
 };
+use half::{bf16, f16};
 use ndarray::prelude::*;
 #[cfg(feature = "backend-ort-directml")]
 use ort::execution_providers::DirectMLExecutionProvider;
+#[cfg(feature = "backend-ort-openvino")]
+use ort::execution_providers::OpenVINOExecutionProvider;
+#[cfg(feature = "backend-ort-xnnpack")]
+use ort::execution_providers::XNNPACKExecutionProvider;
 #[cfg(feature = "backend-ort-cuda")]
 use ort::execution_providers::{
     ArenaExtendStrategy::NextPowerOfTwo, CUDAExecutionProvider,
     cuda::CuDNNConvAlgorithmSearch::Exhaustive,
 };
 use ort::execution_providers::{CPUExecutionProvider, ExecutionProviderDispatch};
-use ort::value::TensorRef;
+use ort::session::builder::{GraphOptimizationLevel, SessionBuilder};
+use ort::tensor::TensorElementType;
+use ort::value::{TensorRef, ValueType};
 use std::ops::Deref;
+#[cfg(not(target_family = "wasm"))]
+use {
+    crate::worker::sha256_hex,
+    std::io::Read,
+    std::path::Path,
+    std::sync::atomic::{AtomicUsize, Ordering},
+};
 
+/// Execution provider of the sessions. The plain CPU provider is always registered last,
+/// so the nodes a provider does not support, or all of them when it fails to register,
+/// run on it.
 #[derive(Debug, Default, Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum RettoOrtWorkerDevice {
@@ -28,6 +46,14 @@ pub enum RettoOrtWorkerDevice {
     /// Use DirectML with the specified device ID
     #[cfg(feature = "backend-ort-directml")]
     DirectML(i32),
+    /// Use XNNPACK with the specified number of threads, ORT picks it when `None`.
+    /// XNNPACK runs its own thread pool, so set [RettoOrtSessionOptions::intra_threads]
+    /// to 1 to avoid contention.
+    #[cfg(feature = "backend-ort-xnnpack")]
+    XNNPACK(Option<usize>),
+    /// Use OpenVINO on the CPU with the specified number of threads
+    #[cfg(feature = "backend-ort-openvino")]
+    OpenVINO(Option<usize>),
 }
 
 #[derive(Debug, Clone)]
@@ -48,64 +74,230 @@ impl Deref for RettoOrtWorkerModelProvider {
     }
 }
 
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum RettoOrtGraphOptimization {
+    Disable,
+    /// Constant folding and redundant node eliminations
+    Basic,
+    /// `Basic` and complex node fusions
+    Extended,
+    #[default]
+    /// `Extended` and layout optimizations
+    All,
+}
+
+impl From<RettoOrtGraphOptimization> for GraphOptimizationLevel {
+    fn from(value: RettoOrtGraphOptimization) -> Self {
+        match value {
+            RettoOrtGraphOptimization::Disable => GraphOptimizationLevel::Disable,
+            RettoOrtGraphOptimization::Basic => GraphOptimizationLevel::Level1,
+            RettoOrtGraphOptimization::Extended => GraphOptimizationLevel::Level2,
+            RettoOrtGraphOptimization::All => GraphOptimizationLevel::Level3,
+        }
+    }
+}
+
+/// ONNX Runtime options of one model session
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RettoOrtSessionOptions {
+    /// Threads running a single operator, ORT uses one per physical core when `None`.
+    /// Lower it when several sessions share a machine, to avoid oversubscription.
+    pub intra_threads: Option<usize>,
+    /// Threads running independent operators with `parallel_execution`,
+    /// ORT picks it when `None`
+    pub inter_threads: Option<usize>,
+    /// Run independent branches of the graph in parallel instead of sequentially
+    pub parallel_execution: bool,
+    pub optimization: RettoOrtGraphOptimization,
+    /// Plan the allocations from the first run, pays off when the input shapes repeat
+    pub memory_pattern: bool,
+    /// Cache CPU allocations in an arena instead of returning them to the system
+    pub arena_allocator: bool,
+}
+
+impl Default for RettoOrtSessionOptions {
+    fn default() -> Self {
+        RettoOrtSessionOptions {
+            intra_threads: None,
+            inter_threads: None,
+            parallel_execution: false,
+            optimization: RettoOrtGraphOptimization::default(),
+            memory_pattern: true,
+            arena_allocator: true,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct RettoOrtWorkerConfig {
     pub device: RettoOrtWorkerDevice,
     pub models: RettoOrtWorkerModelProvider,
+    pub det_session: RettoOrtSessionOptions,
+    pub cls_session: RettoOrtSessionOptions,
+    /// Also applies to the rec models added with [RettoInnerWorker::add_rec_model]
+    pub rec_session: RettoOrtSessionOptions,
+    /// Directory the optimized models are saved into and loaded back from on the next
+    /// starts, skipping the graph optimizations. Entries are keyed by the hash of the
+    /// model, the ONNX Runtime build and the options shaping the optimized graph.
+    /// Entries are never evicted, those of replaced models stay until the directory is
+    /// cleared.
+    #[cfg(not(target_family = "wasm"))]
+    pub optimized_model_cache: Option<String>,
+}
+
+/// Precision of the copies written by [RettoOrtWorkerModelProvider::quantize]
+#[cfg(all(not(target_family = "wasm"), feature = "quantize"))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum RettoOrtQuantization {
+    #[default]
+    /// Dynamic quantization with `onnxruntime.quantization`: the weights are stored as uint8
+    /// and the activations are quantized on the fly
+    Int8,
+    /// Weights and activations converted to float16 with `onnxconverter_common`
+    Fp16,
+}
+
+#[cfg(all(not(target_family = "wasm"), feature = "quantize"))]
+impl RettoOrtQuantization {
+    fn suffix(&self) -> &'static str {
+        match self {
+            RettoOrtQuantization::Int8 => "int8",
+            RettoOrtQuantization::Fp16 => "fp16",
+        }
+    }
+
+    /// Python script converting the model `sys.argv[1]` into `sys.argv[2]`
+    fn script(&self) -> &'static str {
+        match self {
+            RettoOrtQuantization::Int8 => concat!(
+                "import sys\n",
+                "from onnxruntime.quantization import QuantType, quantize_dynamic\n",
+                "quantize_dynamic(sys.argv[1], sys.argv[2], weight_type=QuantType.QUInt8)",
+            ),
+            RettoOrtQuantization::Fp16 => concat!(
+                "import sys, onnx\n",
+                "from onnxconverter_common import float16\n",
+                "model = float16.convert_float_to_float16(onnx.load(sys.argv[1]), keep_io_types=True)\n",
+                "onnx.save(model, sys.argv[2])",
+            ),
+        }
+    }
+}
+
+/// Options of [RettoOrtWorkerModelProvider::quantize]
+#[cfg(all(not(target_family = "wasm"), feature = "quantize"))]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RettoOrtQuantizeConfig {
+    pub precision: RettoOrtQuantization,
+    /// Whether to convert the det model, it is kept as is otherwise
+    pub det: bool,
+    /// Whether to convert the rec model, it is kept as is otherwise
+    pub rec: bool,
+    /// Whether to convert the cls model, it is kept as is otherwise. The cls model is small
+    /// and runs on small crops, so it gains little.
+    pub cls: bool,
+    /// Python interpreter with the `onnxruntime` package, and `onnxconverter-common`
+    /// for [RettoOrtQuantization::Fp16]
+    pub python: String,
+}
+
+#[cfg(all(not(target_family = "wasm"), feature = "quantize"))]
+impl Default for RettoOrtQuantizeConfig {
+    fn default() -> Self {
+        RettoOrtQuantizeConfig {
+            precision: RettoOrtQuantization::default(),
+            det: true,
+            rec: true,
+            cls: false,
+            python: "python3".to_string(),
+        }
+    }
+}
+
+impl RettoOrtWorkerModelProvider {
+    /// Write int8 or fp16 copies of the selected models to `dir`, the I/O of the copies stays
+    /// float32. It runs a Python script, so it is meant for tooling like the CLI.
+    /// Applications can ship pre-converted models instead, they load like any other.
+    #[cfg(all(not(target_family = "wasm"), feature = "quantize"))]
+    pub fn quantize(&self, dir: &str, cfg: &RettoOrtQuantizeConfig) -> RettoResult<Self> {
+        // Tells apart the temporary copies of the blob sources
+        static TEMP_COUNT: AtomicUsize = AtomicUsize::new(0);
+        std::fs::create_dir_all(dir)?;
+        let suffix = cfg.precision.suffix();
+        let quantize =
+            |source: &RettoWorkerModelSource, name: &str, enabled: bool| -> RettoResult<_> {
+                if !enabled {
+                    return Ok(source.clone());
+                }
+                let target = Path::new(dir).join(format!("{name}_{suffix}.onnx"));
+                let (source, temp) = match source.clone().resolve()? {
+                    RettoWorkerModelResolvedSource::Path(path) => {
+                        (Path::new(&path).to_path_buf(), false)
+                    }
+                    RettoWorkerModelResolvedSource::Blob(blob) => {
+                        let path = std::env::temp_dir().join(format!(
+                            "retto-quantize-{}-{}-{name}.onnx",
+                            std::process::id(),
+                            TEMP_COUNT.fetch_add(1, Ordering::Relaxed)
+                        ));
+                        std::fs::write(&path, blob)?;
+                        (path, true)
+                    }
+                };
+                let status = std::process::Command::new(&cfg.python)
+                    .arg("-c")
+                    .arg(cfg.precision.script())
+                    .arg(&source)
+                    .arg(&target)
+                    .status();
+                if temp {
+                    let _ = std::fs::remove_file(&source);
+                }
+                let status = status.map_err(|e| {
+                    RettoError::QuantizeError(format!("cannot run {}: {e}", cfg.python))
+                })?;
+                if !status.success() {
+                    return Err(RettoError::QuantizeError(format!(
+                        "converting the {name} model to {suffix} exited with {status}"
+                    )));
+                }
+                Ok(RettoWorkerModelSource::Path(
+                    target.to_string_lossy().into_owned(),
+                ))
+            };
+        Ok(Self(RettoWorkerModelProvider {
+            det: quantize(&self.det, "det", cfg.det)?,
+            rec: quantize(&self.rec, "rec", cfg.rec)?,
+            cls: quantize(&self.cls, "cls", cfg.cls)?,
+        }))
+    }
 }
 
 impl RettoWorkerModelProviderBuilder for RettoOrtWorkerModelProvider {
     #[cfg(all(not(target_family = "wasm"), feature = "hf-hub"))]
-    fn from_hf_hub_v4_default() -> Self {
-        let hf_repo = "pk5ls20/PaddleModel";
-        Self(RettoWorkerModelProvider {
-            det: RettoWorkerModelSource::HuggingFace {
-                repo: hf_repo.to_string(),
-                model: "retto/onnx/ch_PP-OCRv4_det_infer.onnx".to_string(),
-            },
-            rec: RettoWorkerModelSource::HuggingFace {
-                repo: hf_repo.to_string(),
-                model: "retto/onnx/ch_PP-OCRv4_rec_infer.onnx".to_string(),
-            },
-            cls: RettoWorkerModelSource::HuggingFace {
-                repo: hf_repo.to_string(),
-                model: "retto/onnx/ch_ppocr_mobile_v2.0_cls_infer.onnx".to_string(),
-            },
-        })
+    fn from_hf_hub_v4_language(language: Language) -> RettoResult<Self> {
+        RettoWorkerModelProvider::from_hf_hub_v4_language(language).map(Self)
     }
 
     #[cfg(not(target_family = "wasm"))]
-    fn from_local_v4_path_default() -> Self {
-        Self(RettoWorkerModelProvider {
-            det: RettoWorkerModelSource::Path("ch_PP-OCRv4_det_infer.onnx".into()),
-            rec: RettoWorkerModelSource::Path("ch_PP-OCRv4_rec_infer.onnx".into()),
-            cls: RettoWorkerModelSource::Path("ch_ppocr_mobile_v2.0_cls_infer.onnx".into()),
-        })
+    fn from_local_v4_language_path(language: Language) -> Self {
+        Self(RettoWorkerModelProvider::from_local_v4_language_path(
+            language,
+        ))
     }
 
-    #[cfg(feature = "download-models")]
     fn from_local_v4_blob_default() -> Self {
-        Self(RettoWorkerModelProvider {
-            det: RettoWorkerModelSource::Blob(
-                include_bytes!("../../models/ch_PP-OCRv4_det_infer.onnx").to_vec(),
-            ),
-            rec: RettoWorkerModelSource::Blob(
-                include_bytes!("../../models/ch_PP-OCRv4_rec_infer.onnx").to_vec(),
-            ),
-            cls: RettoWorkerModelSource::Blob(
-                include_bytes!("../../models/ch_ppocr_mobile_v2.0_cls_infer.onnx").to_vec(),
-            ),
-        })
+        Self(RettoWorkerModelProvider::from_local_v4_blob_default())
     }
 
-    #[cfg(not(feature = "download-models"))]
-    fn from_local_v4_blob_default() -> Self {
-        Self(RettoWorkerModelProvider {
-            det: RettoWorkerModelSource::Blob(Vec::new()),
-            rec: RettoWorkerModelSource::Blob(Vec::new()),
-            cls: RettoWorkerModelSource::Blob(Vec::new()),
-        })
+    #[cfg(not(target_family = "wasm"))]
+    fn from_local_v5_path(model: PpOcrV5Model) -> Self {
+        Self(RettoWorkerModelProvider::from_local_v5_path(model))
     }
 }
 
@@ -113,16 +305,110 @@ impl RettoWorkerModelProviderBuilder for RettoOrtWorkerModelProvider {
 pub struct RettoOrtWorker {
     cfg: RettoOrtWorkerConfig,
     det_session: ort::session::Session,
-    rec_session: ort::session::Session,
+    /// The rec model of the provider, then the ones added with
+    /// [RettoInnerWorker::add_rec_model]
+    rec_sessions: Vec<ort::session::Session>,
     cls_session: ort::session::Session,
+    det_io: RettoWorkerModelIO,
+    rec_ios: Vec<RettoWorkerModelIO>,
+    cls_io: RettoWorkerModelIO,
+}
+
+fn ort_session_io(session: &ort::session::Session) -> RettoResult<RettoWorkerModelIO> {
+    let shape = |name: &str, value_type: &ValueType| {
+        match value_type.tensor_type() {
+            Some(
+                TensorElementType::Float32
+                | TensorElementType::Float16
+                | TensorElementType::Bfloat16,
+            ) => {}
+            Some(ty) => {
+                return Err(RettoError::ModelConfigMismatchError(format!(
+                    "{name} is a {ty:?} tensor, only float ones are supported"
+                )));
+            }
+            None => {}
+        }
+        value_type
+            .tensor_shape()
+            .map(|shape| shape.iter().map(|&d| usize::try_from(d).ok()).collect())
+            .ok_or_else(|| RettoError::ModelConfigMismatchError(format!("{name} is not a tensor")))
+    };
+    let (input, output) = session
+        .inputs
+        .first()
+        .zip(session.outputs.first())
+        .ok_or_else(|| {
+            RettoError::ModelConfigMismatchError("model has no input or output".to_string())
+        })?;
+    Ok(RettoWorkerModelIO {
+        input_name: input.name.clone(),
+        input_shape: shape(&input.name, &input.input_type)?,
+        output_name: output.name.clone(),
+        output_shape: shape(&output.name, &output.output_type)?,
+    })
+}
+
+/// Run `session`, converting from and to f32 at the boundary when the model takes or
+/// returns half-precision tensors. Quantized models (dynamic or QDQ) keep float32 I/O.
+fn run_ort_session<D: Dimension>(
+    session: &mut ort::session::Session,
+    io: &RettoWorkerModelIO,
+    input: Array4<f32>,
+) -> RettoResult<Array<f32, D>> {
+    let input_name = io.input_name.as_str();
+    let outputs = match session.inputs[0].input_type.tensor_type() {
+        Some(TensorElementType::Float16) => {
+            let input = input.mapv(f16::from_f32);
+            session.run(ort::inputs! { input_name => TensorRef::from_array_view(&input)? })?
+        }
+        Some(TensorElementType::Bfloat16) => {
+            let input = input.mapv(bf16::from_f32);
+            session.run(ort::inputs! { input_name => TensorRef::from_array_view(&input)? })?
+        }
+        _ => session.run(ort::inputs! {
+            input_name => TensorRef::from_array_view(&input.as_standard_layout())?
+        })?,
+    };
+    let output = &outputs[io.output_name.as_str()];
+    let output = match output.dtype().tensor_type() {
+        Some(TensorElementType::Float16) => output.try_extract_array::<f16>()?.mapv(f16::to_f32),
+        Some(TensorElementType::Bfloat16) => output.try_extract_array::<bf16>()?.mapv(bf16::to_f32),
+        _ => output.try_extract_array::<f32>()?.to_owned(),
+    };
+    Ok(output.into_dimensionality::<D>()?)
+}
+
+fn ort_session_builder(
+    device: &RettoOrtWorkerDevice,
+    options: &RettoOrtSessionOptions,
+) -> RettoResult<SessionBuilder> {
+    let mut builder = ort::session::Session::builder()?
+        .with_execution_providers(ort_execution_providers(device, options))?
+        .with_optimization_level(options.optimization.into())?
+        .with_parallel_execution(options.parallel_execution)?
+        .with_memory_pattern(options.memory_pattern)?;
+    if let Some(threads) = options.intra_threads {
+        builder = builder.with_intra_threads(threads)?;
+    }
+    if let Some(threads) = options.inter_threads {
+        builder = builder.with_inter_threads(threads)?;
+    }
+    Ok(builder)
 }
 
 fn build_ort_session(
     model_source: RettoWorkerModelSource,
-    providers: &[ExecutionProviderDispatch],
+    role: &str,
+    cfg: &RettoOrtWorkerConfig,
+    options: &RettoOrtSessionOptions,
 ) -> RettoResult<ort::session::Session> {
-    let builder = ort::session::Session::builder()?.with_execution_providers(providers)?;
-    let model_source = model_source.resolve()?;
+    let model_source = model_source.resolve_as(role)?;
+    #[cfg(not(target_family = "wasm"))]
+    if let Some(cache_dir) = &cfg.optimized_model_cache {
+        return cached_ort_session(model_source, cfg, options, Path::new(cache_dir));
+    }
+    let builder = ort_session_builder(&cfg.device, options)?;
     match model_source {
         #[cfg(not(target_family = "wasm"))]
         RettoWorkerModelResolvedSource::Path(path) => {
@@ -134,6 +420,79 @@ fn build_ort_session(
     }
 }
 
+/// Cache key of the optimized copy of `blob`. Optimized graphs may hold nodes specific to
+/// the ORT build and execution provider.
+#[cfg(not(target_family = "wasm"))]
+fn optimized_model_key(
+    blob: &[u8],
+    cfg: &RettoOrtWorkerConfig,
+    options: &RettoOrtSessionOptions,
+) -> RettoResult<String> {
+    let build = format!(
+        "{}|{:?}|{:?}",
+        ort::info(),
+        cfg.device,
+        options.optimization
+    );
+    sha256_hex(blob.chain(build.as_bytes()))
+}
+
+/// Session of the optimized copy of `model_source` in `cache_dir`, which is written on the
+/// first start. A changed source model gets a new key, so stale copies are never loaded.
+#[cfg(not(target_family = "wasm"))]
+fn cached_ort_session(
+    model_source: RettoWorkerModelResolvedSource,
+    cfg: &RettoOrtWorkerConfig,
+    options: &RettoOrtSessionOptions,
+    cache_dir: &Path,
+) -> RettoResult<ort::session::Session> {
+    // Tells apart the partial files of the sessions built concurrently by this process
+    static PARTIAL_COUNT: AtomicUsize = AtomicUsize::new(0);
+    let blob = match model_source {
+        RettoWorkerModelResolvedSource::Path(path) => std::fs::read(path)?,
+        RettoWorkerModelResolvedSource::Blob(blob) => blob,
+    };
+    let key = optimized_model_key(&blob, cfg, options)?;
+    let cached = cache_dir.join(format!("{key}.onnx"));
+    if cached.is_file() {
+        let options = RettoOrtSessionOptions {
+            optimization: RettoOrtGraphOptimization::Disable,
+            ..options.clone()
+        };
+        match ort_session_builder(&cfg.device, &options)?.commit_from_file(&cached) {
+            Ok(session) => return Ok(session),
+            Err(e) => tracing::warn!("Cannot load {}, optimizing again: {e}", cached.display()),
+        }
+    }
+    std::fs::create_dir_all(cache_dir)?;
+    // Concurrent starts write their own file and the rename is atomic
+    let partial = cache_dir.join(format!(
+        "{key}.{}.{}.partial",
+        std::process::id(),
+        PARTIAL_COUNT.fetch_add(1, Ordering::Relaxed)
+    ));
+    let session = ort_session_builder(&cfg.device, options)?
+        .with_optimized_model_path(&partial)
+        .and_then(|builder| builder.commit_from_memory(&blob));
+    let session = match session {
+        Ok(session) => session,
+        Err(e) => {
+            // ORT may have written the optimized model before failing
+            let _ = std::fs::remove_file(&partial);
+            return Err(e.into());
+        }
+    };
+    // The session is usable without its cached copy
+    if let Err(e) = std::fs::rename(&partial, &cached) {
+        tracing::warn!(
+            "Cannot cache the optimized model to {}: {e}",
+            cached.display()
+        );
+        let _ = std::fs::remove_file(&partial);
+    }
+    Ok(session)
+}
+
 impl RettoWorker for RettoOrtWorker {
     type RettoWorkerModelProvider = RettoOrtWorkerModelProvider;
     type RettoWorkerConfig = RettoOrtWorkerConfig;
@@ -149,32 +508,16 @@ impl RettoWorker for RettoOrtWorker {
                 .commit()
                 .expect("Cannot initialize ort.");
         }
-        let mut providers = Vec::new();
-        match cfg.device {
-            #[cfg(feature = "backend-ort-cuda")]
-            RettoOrtWorkerDevice::Cuda(id) => providers.push(
-                CUDAExecutionProvider::default()
-                    .with_arena_extend_strategy(NextPowerOfTwo)
-                    .with_conv_algorithm_search(Exhaustive)
-                    .with_device_id(id)
-                    .build(),
-            ),
-            #[cfg(feature = "backend-ort-directml")]
-            RettoOrtWorkerDevice::DirectML(id) => providers.push(
-                DirectMLExecutionProvider::default()
-                    .with_device_id(id)
-                    .build(),
-            ),
-            _ => {}
-        };
-        providers.push(CPUExecutionProvider::default().build());
-        let det_session = build_ort_session(cfg.models.det.clone(), &providers)?;
-        let cls_session = build_ort_session(cfg.models.cls.clone(), &providers)?;
-        let rec_session = build_ort_session(cfg.models.rec.clone(), &providers)?;
+        let det_session = build_ort_session(cfg.models.det.clone(), "det", &cfg, &cfg.det_session)?;
+        let cls_session = build_ort_session(cfg.models.cls.clone(), "cls", &cfg, &cfg.cls_session)?;
+        let rec_session = build_ort_session(cfg.models.rec.clone(), "rec", &cfg, &cfg.rec_session)?;
         let worker = RettoOrtWorker {
             cfg,
+            det_io: ort_session_io(&det_session)?,
+            rec_ios: vec![ort_session_io(&rec_session)?],
+            cls_io: ort_session_io(&cls_session)?,
             det_session,
-            rec_session,
+            rec_sessions: vec![rec_session],
             cls_session,
         };
         Ok(worker)
@@ -185,37 +528,296 @@ impl RettoWorker for RettoOrtWorker {
     }
 }
 
+/// Providers of `device`, then the plain CPU one. ort does not fail the session when a
+/// provider cannot register, e.g. when it is missing from the ONNX Runtime build, it logs
+/// the error and moves on to the next one.
+fn ort_execution_providers(
+    device: &RettoOrtWorkerDevice,
+    options: &RettoOrtSessionOptions,
+) -> Vec<ExecutionProviderDispatch> {
+    let mut providers = Vec::new();
+    match *device {
+        #[cfg(feature = "backend-ort-cuda")]
+        RettoOrtWorkerDevice::Cuda(id) => providers.push(
+            CUDAExecutionProvider::default()
+                .with_arena_extend_strategy(NextPowerOfTwo)
+                .with_conv_algorithm_search(Exhaustive)
+                .with_device_id(id)
+                .build(),
+        ),
+        #[cfg(feature = "backend-ort-directml")]
+        RettoOrtWorkerDevice::DirectML(id) => providers.push(
+            DirectMLExecutionProvider::default()
+                .with_device_id(id)
+                .build(),
+        ),
+        #[cfg(feature = "backend-ort-xnnpack")]
+        RettoOrtWorkerDevice::XNNPACK(threads) => {
+            let mut provider = XNNPACKExecutionProvider::default();
+            if let Some(threads) = threads.and_then(std::num::NonZeroUsize::new) {
+                provider = provider.with_intra_op_num_threads(threads);
+            }
+            providers.push(provider.build());
+        }
+        #[cfg(feature = "backend-ort-openvino")]
+        RettoOrtWorkerDevice::OpenVINO(threads) => {
+            let mut provider = OpenVINOExecutionProvider::default().with_device_type("CPU");
+            if let Some(threads) = threads {
+                provider = provider.with_num_threads(threads);
+            }
+            providers.push(provider.build());
+        }
+        _ => {}
+    };
+    providers.push(
+        CPUExecutionProvider::default()
+            .with_arena_allocator(options.arena_allocator)
+            .build(),
+    );
+    providers
+}
+
 impl RettoInnerWorker for RettoOrtWorker {
     fn det(&mut self, input: Array4<f32>) -> RettoResult<Array4<f32>> {
-        let outputs = self.det_session.run(ort::inputs! {
-            "x" => TensorRef::from_array_view(&input.as_standard_layout())?
-        })?;
-        let val = &outputs[0]
-            .try_extract_array::<f32>()?
-            .into_dimensionality::<Ix4>()?;
-        let output = val.to_owned();
-        Ok(output)
+        run_ort_session(&mut self.det_session, &self.det_io, input)
     }
 
     fn cls(&mut self, input: Array4<f32>) -> RettoResult<Array2<f32>> {
-        let outputs = self.cls_session.run(ort::inputs! {
-            "x" => TensorRef::from_array_view(&input.as_standard_layout())?
+        run_ort_session(&mut self.cls_session, &self.cls_io, input)
+    }
+
+    fn rec(&mut self, model: usize, input: Array4<f32>) -> RettoResult<Array3<f32>> {
+        run_ort_session(&mut self.rec_sessions[model], &self.rec_ios[model], input)
+    }
+
+    fn rec_metadata(&self, model: usize, key: &str) -> RettoResult<Option<String>> {
+        Ok(self.rec_sessions[model].metadata()?.custom(key)?)
+    }
+
+    fn add_rec_model(&mut self, source: RettoWorkerModelSource) -> RettoResult<usize> {
+        let session = build_ort_session(source, "rec", &self.cfg, &self.cfg.rec_session)?;
+        self.rec_ios.push(ort_session_io(&session)?);
+        self.rec_sessions.push(session);
+        Ok(self.rec_sessions.len() - 1)
+    }
+
+    fn det_io(&self) -> &RettoWorkerModelIO {
+        &self.det_io
+    }
+
+    fn cls_io(&self) -> &RettoWorkerModelIO {
+        &self.cls_io
+    }
+
+    fn rec_io(&self, model: usize) -> &RettoWorkerModelIO {
+        &self.rec_ios[model]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::worker::tests::{identity_onnx, typed_identity_onnx};
+    use rstest::*;
+
+    fn identity_models() -> RettoOrtWorkerModelProvider {
+        let source = || RettoWorkerModelSource::Blob(identity_onnx(&[]));
+        RettoOrtWorkerModelProvider(RettoWorkerModelProvider {
+            det: source(),
+            rec: source(),
+            cls: source(),
+        })
+    }
+
+    fn input() -> Array4<f32> {
+        Array4::from_shape_fn((1, 3, 4, 5), |(_, c, h, w)| (c * 20 + h * 5 + w) as f32)
+    }
+
+    #[rstest]
+    #[case::default(RettoOrtSessionOptions::default())]
+    #[case::tuned(RettoOrtSessionOptions {
+        intra_threads: Some(1),
+        inter_threads: Some(2),
+        parallel_execution: true,
+        optimization: RettoOrtGraphOptimization::Disable,
+        memory_pattern: false,
+        arena_allocator: false,
+    })]
+    fn test_session_options(#[case] options: RettoOrtSessionOptions) -> RettoResult<()> {
+        let mut worker = RettoOrtWorker::new(RettoOrtWorkerConfig {
+            models: identity_models(),
+            det_session: options.clone(),
+            cls_session: options.clone(),
+            rec_session: options,
+            ..Default::default()
         })?;
-        let val = &outputs[0]
-            .try_extract_array::<f32>()?
-            .into_dimensionality::<Ix2>()?;
-        let output = val.to_owned();
-        Ok(output)
+        assert_eq!(worker.det(input())?, input());
+        Ok(())
+    }
+
+    fn cache_entries(dir: &Path) -> RettoResult<Vec<String>> {
+        let mut entries = std::fs::read_dir(dir)?
+            .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+            .collect::<RettoResult<Vec<_>>>()?;
+        entries.sort();
+        Ok(entries)
+    }
+
+    #[test]
+    fn test_optimized_model_cache() -> RettoResult<()> {
+        let dir = std::env::temp_dir().join(format!("retto-ort-cache-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let cfg = RettoOrtWorkerConfig {
+            optimized_model_cache: Some(dir.to_string_lossy().into_owned()),
+            ..Default::default()
+        };
+        let options = RettoOrtSessionOptions::default();
+        let session = |model: Vec<u8>| {
+            let mut session =
+                build_ort_session(RettoWorkerModelSource::Blob(model), "det", &cfg, &options)?;
+            let io = ort_session_io(&session)?;
+            assert_eq!(run_ort_session::<Ix4>(&mut session, &io, input())?, input());
+            Ok::<_, RettoError>(())
+        };
+        let key = |model: &[u8]| optimized_model_key(model, &cfg, &options);
+        let model = identity_onnx(&[]);
+        // miss: the optimized copy is written, without a partial file left over
+        session(model.clone())?;
+        let entry = format!("{}.onnx", key(&model)?);
+        assert_eq!(cache_entries(&dir)?, [entry.as_str()]);
+        let written = std::fs::metadata(dir.join(&entry))?.modified()?;
+        // hit: the copy is loaded as is
+        session(model.clone())?;
+        assert_eq!(std::fs::metadata(dir.join(&entry))?.modified()?, written);
+        // a corrupted copy is optimized again
+        std::fs::write(dir.join(&entry), b"corrupted")?;
+        session(model.clone())?;
+        assert!(std::fs::metadata(dir.join(&entry))?.len() > 9);
+        // invalidation: a changed model gets its own entry
+        let changed = identity_onnx(&[("version", "2")]);
+        assert_ne!(key(&changed)?, key(&model)?);
+        session(changed.clone())?;
+        assert_eq!(cache_entries(&dir)?.len(), 2);
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[rstest]
+    #[case::invalid_model(b"not a model".to_vec(), false)]
+    #[case::rename_failure(identity_onnx(&[]), true)]
+    fn test_optimized_model_cache_failures(
+        #[case] model: Vec<u8>,
+        #[case] builds: bool,
+    ) -> RettoResult<()> {
+        let dir = std::env::temp_dir().join(format!(
+            "retto-ort-cache-failure-{}-{builds}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        let cfg = RettoOrtWorkerConfig {
+            optimized_model_cache: Some(dir.to_string_lossy().into_owned()),
+            ..Default::default()
+        };
+        let options = RettoOrtSessionOptions::default();
+        // A non-empty directory in place of the cached copy makes the rename fail
+        let blocker = dir.join(format!(
+            "{}.onnx",
+            optimized_model_key(&model, &cfg, &options)?
+        ));
+        std::fs::create_dir_all(blocker.join("blocker"))?;
+        let session = cached_ort_session(
+            RettoWorkerModelResolvedSource::Blob(model),
+            &cfg,
+            &options,
+            &dir,
+        );
+        assert_eq!(session.is_ok(), builds);
+        // no partial file is left over either way
+        let blocker = blocker.file_name().unwrap().to_string_lossy().into_owned();
+        assert_eq!(cache_entries(&dir)?, [blocker]);
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
     }
 
-    fn rec(&mut self, input: Array4<f32>) -> RettoResult<Array3<f32>> {
-        let outputs = self.rec_session.run(ort::inputs! {
-            "x" => TensorRef::from_array_view(&input.as_standard_layout())?
+    #[rstest]
+    #[case::cpu(RettoOrtWorkerDevice::CPU)]
+    #[cfg_attr(
+        feature = "backend-ort-xnnpack",
+        case::xnnpack(RettoOrtWorkerDevice::XNNPACK(Some(1)))
+    )]
+    #[cfg_attr(
+        feature = "backend-ort-openvino",
+        case::openvino(RettoOrtWorkerDevice::OpenVINO(None))
+    )]
+    fn test_cpu_fallback(#[case] device: RettoOrtWorkerDevice) -> RettoResult<()> {
+        let providers = ort_execution_providers(&device, &RettoOrtSessionOptions::default());
+        let last = providers.last().map(|provider| format!("{provider:?}"));
+        assert!(last.is_some_and(|name| name.starts_with("CPUExecutionProvider")));
+        // Runs even with an ONNX Runtime build lacking the provider
+        let mut worker = RettoOrtWorker::new(RettoOrtWorkerConfig {
+            device,
+            models: identity_models(),
+            ..Default::default()
         })?;
-        let val = &outputs[0]
-            .try_extract_array::<f32>()?
-            .into_dimensionality::<Ix3>()?;
-        let output = val.to_owned();
-        Ok(output)
+        assert_eq!(worker.det(input())?, input());
+        Ok(())
+    }
+
+    #[rstest]
+    #[case::f32(1)]
+    #[case::f16(10)]
+    #[case::bf16(16)]
+    fn test_half_precision_round_trip(#[case] elem_type: u64) -> RettoResult<()> {
+        let mut session = ort_session_builder(
+            &RettoOrtWorkerDevice::CPU,
+            &RettoOrtSessionOptions::default(),
+        )?
+        .commit_from_memory(&typed_identity_onnx(elem_type, &[]))?;
+        let io = ort_session_io(&session)?;
+        // Integers below 256 are exact in every precision
+        assert_eq!(run_ort_session::<Ix4>(&mut session, &io, input())?, input());
+        Ok(())
+    }
+
+    #[cfg(feature = "quantize")]
+    #[rstest]
+    #[case::int8(RettoOrtQuantization::Int8, "int8")]
+    #[case::fp16(RettoOrtQuantization::Fp16, "fp16")]
+    fn test_quantize_selection(
+        #[case] precision: RettoOrtQuantization,
+        #[case] suffix: &str,
+    ) -> RettoResult<()> {
+        let dir = std::env::temp_dir().join(format!("retto-quantized-{}", std::process::id()));
+        let temp_files = || -> RettoResult<usize> {
+            let prefix = format!("retto-quantize-{}-", std::process::id());
+            Ok(std::fs::read_dir(std::env::temp_dir())?
+                .filter_map(Result::ok)
+                .filter(|entry| entry.file_name().to_string_lossy().starts_with(&prefix))
+                .count())
+        };
+        let before = temp_files()?;
+        // `true` stands for an interpreter whose conversion succeeds
+        let models = identity_models().quantize(
+            &dir.to_string_lossy(),
+            &RettoOrtQuantizeConfig {
+                precision,
+                python: "true".to_string(),
+                ..Default::default()
+            },
+        )?;
+        let path = |source: &RettoWorkerModelSource| match source {
+            RettoWorkerModelSource::Path(path) => Some(path.clone()),
+            _ => None,
+        };
+        for (source, name) in [(&models.det, "det"), (&models.rec, "rec")] {
+            let target = dir.join(format!("{name}_{suffix}.onnx"));
+            assert_eq!(path(source), Some(target.to_string_lossy().into_owned()));
+        }
+        // the cls model is kept as is by default
+        assert!(matches!(&models.cls, RettoWorkerModelSource::Blob(_)));
+        // the temporary copies of the blob sources are removed
+        assert_eq!(temp_files()?, before);
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
     }
 }
@@ -0,0 +1,191 @@
+use crate::error::{RettoError, RettoResult};
+use crate::serde::*;
+use crate::worker::{
+    RettoInnerWorker, RettoWorker, RettoWorkerModelIO, RettoWorkerModelProvider,
+    RettoWorkerModelResolvedSource, RettoWorkerModelSource,
+};
+use ndarray::prelude::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tract_onnx::prelude::*;
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RettoTractWorkerConfig {
+    pub models: RettoWorkerModelProvider,
+}
+
+/// Optimized ONNX model with its signature and custom metadata
+#[derive(Debug)]
+struct TractModel {
+    plan: Arc<TypedRunnableModel>,
+    io: RettoWorkerModelIO,
+    /// Index of [RettoWorkerModelIO::output_name] in the outputs of `plan`
+    output: usize,
+    metadata: HashMap<String, String>,
+}
+
+impl TractModel {
+    fn load(source: RettoWorkerModelSource, role: &str) -> RettoResult<Self> {
+        let onnx = tract_onnx::onnx();
+        let proto = match source.resolve_as(role)? {
+            #[cfg(not(target_family = "wasm"))]
+            RettoWorkerModelResolvedSource::Path(path) => onnx.proto_model_for_path(path)?,
+            RettoWorkerModelResolvedSource::Blob(blob) => {
+                onnx.proto_model_for_read(&mut blob.as_slice())?
+            }
+        };
+        let metadata = proto
+            .metadata_props
+            .iter()
+            .map(|prop| (prop.key.clone(), prop.value.clone()))
+            .collect();
+        // Read the signature before optimizing, which may rename the nodes
+        let model = onnx.model_for_proto_model(&proto)?.into_typed()?;
+        let io = Self::model_io(&model)?;
+        let plan = model.into_optimized()?.into_runnable()?;
+        let output = Self::output_index(plan.model(), &io.output_name)?;
+        Ok(TractModel {
+            plan,
+            io,
+            output,
+            metadata,
+        })
+    }
+
+    fn outlet_name(model: &TypedModel, outlet: OutletId) -> String {
+        model
+            .outlet_label(outlet)
+            .map_or_else(|| model.node(outlet.node).name.clone(), str::to_owned)
+    }
+
+    fn output_index(model: &TypedModel, name: &str) -> RettoResult<usize> {
+        model
+            .outputs
+            .iter()
+            .position(|&outlet| Self::outlet_name(model, outlet) == name)
+            .ok_or_else(|| {
+                RettoError::ModelConfigMismatchError(format!(
+                    "output `{name}` not found in the optimized model"
+                ))
+            })
+    }
+
+    fn model_io(model: &TypedModel) -> RettoResult<RettoWorkerModelIO> {
+        let shape = |fact: &TypedFact| {
+            fact.shape
+                .iter()
+                .map(|d| d.to_i64().ok().and_then(|d| usize::try_from(d).ok()))
+                .collect::<Vec<_>>()
+        };
+        let (&input, &output) =
+            model
+                .inputs
+                .first()
+                .zip(model.outputs.first())
+                .ok_or_else(|| {
+                    RettoError::ModelConfigMismatchError("model has no input or output".to_string())
+                })?;
+        Ok(RettoWorkerModelIO {
+            input_name: Self::outlet_name(model, input),
+            input_shape: shape(model.outlet_fact(input)?),
+            output_name: Self::outlet_name(model, output),
+            output_shape: shape(model.outlet_fact(output)?),
+        })
+    }
+
+    /// Go through raw buffers, tract may depend on another ndarray release than ours
+    fn run<D: Dimension>(&self, input: Array4<f32>) -> RettoResult<Array<f32, D>> {
+        let data = input.iter().copied().collect::<Vec<_>>();
+        let tensor = Tensor::from_shape(input.shape(), &data)?;
+        let outputs = self.plan.run(tvec!(tensor.into()))?;
+        let output = &outputs[self.output];
+        let output = ArrayD::from_shape_vec(
+            output.shape(),
+            output.try_as_plain_ram()?.as_slice::<f32>()?.to_vec(),
+        )?;
+        Ok(output.into_dimensionality::<D>()?)
+    }
+}
+
+/// Pure-Rust backend on top of tract, it reads the same ONNX models as
+/// [crate::worker::ort_worker::RettoOrtWorker] without a native runtime
+#[derive(Debug)]
+pub struct RettoTractWorker {
+    det: TractModel,
+    cls: TractModel,
+    /// The rec model of the provider, then the ones added with
+    /// [RettoInnerWorker::add_rec_model]
+    recs: Vec<TractModel>,
+}
+
+impl RettoWorker for RettoTractWorker {
+    type RettoWorkerModelProvider = RettoWorkerModelProvider;
+    type RettoWorkerConfig = RettoTractWorkerConfig;
+
+    fn new(cfg: Self::RettoWorkerConfig) -> RettoResult<Self>
+    where
+        Self: Sized,
+    {
+        let det = TractModel::load(cfg.models.det.clone(), "det")?;
+        let cls = TractModel::load(cfg.models.cls.clone(), "cls")?;
+        let rec = TractModel::load(cfg.models.rec.clone(), "rec")?;
+        Ok(RettoTractWorker {
+            det,
+            cls,
+            recs: vec![rec],
+        })
+    }
+
+    fn init(&self) -> RettoResult<()> {
+        Ok(())
+    }
+}
+
+impl RettoInnerWorker for RettoTractWorker {
+    fn det(&mut self, input: Array4<f32>) -> RettoResult<Array4<f32>> {
+        self.det.run(input)
+    }
+
+    fn cls(&mut self, input: Array4<f32>) -> RettoResult<Array2<f32>> {
+        self.cls.run(input)
+    }
+
+    fn rec(&mut self, model: usize, input: Array4<f32>) -> RettoResult<Array3<f32>> {
+        self.recs[model].run(input)
+    }
+
+    fn rec_metadata(&self, model: usize, key: &str) -> RettoResult<Option<String>> {
+        Ok(self.recs[model].metadata.get(key).cloned())
+    }
+
+    fn add_rec_model(&mut self, source: RettoWorkerModelSource) -> RettoResult<usize> {
+        self.recs.push(TractModel::load(source, "rec")?);
+        Ok(self.recs.len() - 1)
+    }
+
+    fn det_io(&self) -> &RettoWorkerModelIO {
+        &self.det.io
+    }
+
+    fn cls_io(&self) -> &RettoWorkerModelIO {
+        &self.cls.io
+    }
+
+    fn rec_io(&self, model: usize) -> &RettoWorkerModelIO {
+        &self.recs[model].io
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::worker::tests::{check_identity_worker, identity_provider};
+
+    #[test]
+    fn test_identity_model() -> RettoResult<()> {
+        check_identity_worker(RettoTractWorker::new(RettoTractWorkerConfig {
+            models: identity_provider(),
+        })?)
+    }
+}
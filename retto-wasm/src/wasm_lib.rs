@@ -19,6 +19,18 @@ static GLOBAL_TRACING: Lazy<Mutex<()>> = Lazy::new(|| {
 static GLOBAL_SESSION: Lazy<Mutex<Option<RettoSession<RettoOrtWorker>>>> =
     Lazy::new(|| Mutex::new(None));
 
+/// XNNPACK when built in, ort falls back to the CPU provider if it fails to register
+fn wasm_device() -> RettoOrtWorkerDevice {
+    #[cfg(feature = "xnnpack")]
+    {
+        RettoOrtWorkerDevice::XNNPACK(None)
+    }
+    #[cfg(not(feature = "xnnpack"))]
+    {
+        RettoOrtWorkerDevice::CPU
+    }
+}
+
 #[unsafe(no_mangle)]
 pub extern "C" fn alloc(size: usize) -> *mut c_void {
     unsafe {
@@ -87,12 +99,13 @@ pub unsafe extern "C" fn retto_init(
     guard.get_or_insert_with(|| {
         RettoSession::new(RettoSessionConfig {
             worker_config: RettoOrtWorkerConfig {
-                device: RettoOrtWorkerDevice::CPU,
+                device: wasm_device(),
                 models: RettoOrtWorkerModelProvider(RettoWorkerModelProvider {
                     det: RettoWorkerModelSource::Blob(det_model),
                     rec: RettoWorkerModelSource::Blob(rec_model),
                     cls: RettoWorkerModelSource::Blob(cls_model),
                 }),
+                ..Default::default()
             },
             rec_processor_config: RecProcessorConfig {
                 character_source: RecCharacterDictProvider::OutSide(RettoWorkerModelSource::Blob(
@@ -116,8 +129,9 @@ pub unsafe extern "C" fn retto_embed_init() {
     guard.get_or_insert_with(|| {
         RettoSession::new(RettoSessionConfig {
             worker_config: RettoOrtWorkerConfig {
-                device: RettoOrtWorkerDevice::CPU,
+                device: wasm_device(),
                 models: RettoOrtWorkerModelProvider::from_local_v4_blob_default(),
+                ..Default::default()
             },
             ..Default::default()
         })